@@ -0,0 +1,77 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct AudioSource {
+  description: String,
+  id: usize,
+}
+
+use crate::error::KaptError;
+use std::process::{Child, Command, Stdio};
+pub fn get_audio_sources() -> Result<Vec<AudioSource>, KaptError> {
+  let sources_descriptions: Vec<String> = {
+    let list_sources_child = Command::new("pactl")
+      .args(["list", "sources"])
+      .stdout(Stdio::piped())
+      .spawn();
+    let sources_descriptions = String::from_utf8_lossy(&match list_sources_child {
+      Ok(Child {
+        stdout: Some(stdout),
+        ..
+      }) => {
+        Command::new("grep")
+          .args(["-e", "device.description"])
+          .stdin(stdout)
+          .output()
+          .map_err(|e| KaptError::process_spawn("grep", e))?
+          .stdout
+      }
+      _ => Vec::new(),
+    })
+    .to_string();
+    sources_descriptions
+      .split("\n")
+      .map(|s| {
+        s.trim()
+          .replace("device.description = ", "")
+          .replace("\"", "")
+      })
+      .filter(|s| !s.is_empty())
+      .collect()
+  };
+
+  let sources_ids: Vec<usize> = {
+    let list_sources_child = Command::new("pactl")
+      .args(["list", "sources"])
+      .stdout(Stdio::piped())
+      .spawn();
+    let sources_ids = String::from_utf8_lossy(&match list_sources_child {
+      Ok(Child {
+        stdout: Some(stdout),
+        ..
+      }) => {
+        Command::new("grep")
+          .args(["-e", "Source #"])
+          .stdin(stdout)
+          .output()
+          .map_err(|e| KaptError::process_spawn("grep", e))?
+          .stdout
+      }
+      _ => Vec::new(),
+    })
+    .to_string();
+    sources_ids
+      .split("\n")
+      .map(|s| s.trim().replace("Source #", ""))
+      .filter(|s| !s.is_empty())
+      .filter_map(|s| s.parse::<usize>().ok())
+      .collect()
+  };
+
+  let mut audio_devices: Vec<AudioSource> = vec![];
+  for (description, id) in sources_descriptions.into_iter().zip(sources_ids) {
+    audio_devices.push(AudioSource { description, id })
+  }
+
+  Ok(audio_devices)
+}
@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::display;
+use crate::display::{CaptureGeometry, CaptureMode};
+use crate::encoding::EncodingProfile;
+use crate::error::KaptError;
+#[cfg(feature = "pipewire")]
+pub use crate::pipewire::PipeWireBackend;
+use crate::state::FfmpegSegmentedStream;
+use nanoid::nanoid;
+
+// The length of each recording segment
+pub const SEGMENT_SECONDS: u32 = 5;
+
+// The arguments for the segment muxer; segment files are named after the unix time at which
+// they were started, and ffmpeg appends each finished segment to the CSV list at `segment_list_path`
+fn segment_args(segment_format: &str, segment_list_path: &str) -> Vec<String> {
+  vec![
+    "-f".into(),
+    "segment".into(),
+    "-segment_time".into(),
+    SEGMENT_SECONDS.to_string(),
+    "-segment_format".into(),
+    segment_format.into(),
+    "-segment_list".into(),
+    segment_list_path.into(),
+    "-segment_list_type".into(),
+    "csv".into(),
+    "-reset_timestamps".into(),
+    "1".into(),
+    "-strftime".into(),
+    "1".into(),
+  ]
+}
+
+// Starts an ffmpeg process which records the video from `input_args` into segments
+pub fn start_segmented_video(
+  segment_dir: &str,
+  input_args: Vec<String>,
+  capture_geometry: &CaptureGeometry,
+  encoding_profile: &EncodingProfile,
+) -> Result<FfmpegSegmentedStream, KaptError> {
+  // Following a window restarts the video stream, so each stream gets its own file names
+  let stream_id = nanoid!(8);
+  let segment_list_path = Path::new(segment_dir)
+    .join(format!("video-{}.csv", stream_id))
+    .to_string_lossy()
+    .to_string();
+  let segment_path = Path::new(segment_dir)
+    .join(format!("video-{}-%s.mkv", stream_id))
+    .to_string_lossy()
+    .to_string();
+
+  let mut command = Command::new("ffmpeg");
+  command.arg("-nostats");
+
+  // Video
+  command.args(input_args);
+
+  // Encoding
+  command.args(encoding_profile.video_args());
+
+  // The segment muxer can only split on keyframes
+  command.args([
+    "-g",
+    &(encoding_profile.framerate * SEGMENT_SECONDS).to_string(),
+  ]);
+
+  // Splitting the video into segments
+  command.args(segment_args("matroska", &segment_list_path));
+  command.arg(&segment_path);
+
+  FfmpegSegmentedStream::spawn(command, segment_list_path, Some(capture_geometry.clone()))
+}
+
+// Starts an ffmpeg process which records the audio from `input_args` into segments
+pub fn start_segmented_audio(
+  segment_dir: &str,
+  input_args: Vec<String>,
+) -> Result<FfmpegSegmentedStream, KaptError> {
+  let segment_list_path = Path::new(segment_dir)
+    .join("audio.csv")
+    .to_string_lossy()
+    .to_string();
+  let segment_path = Path::new(segment_dir)
+    .join("audio-%s.wav")
+    .to_string_lossy()
+    .to_string();
+
+  let mut command = Command::new("ffmpeg");
+  command.arg("-nostats");
+
+  // Audio
+  command.args(input_args);
+  command.args(["-fflags", "+genpts"]);
+  command.args(["-async", "1"]);
+  command.args(["-vsync", "1"]);
+
+  // Splitting the audio into segments
+  command.args(segment_args("wav", &segment_list_path));
+  command.arg(&segment_path);
+
+  FfmpegSegmentedStream::spawn(command, segment_list_path, None)
+}
+
+// The ffmpeg input arguments for recording a PulseAudio source
+fn pulse_input_args(audio_source: usize) -> Vec<String> {
+  vec![
+    "-f".into(),
+    "pulse".into(),
+    "-i".into(),
+    audio_source.to_string(),
+  ]
+}
+
+// A source of video and audio for the recording session. The streams it starts are stopped
+// with `FfmpegSegmentedStream::stop`, and report their start timestamps through ffmpeg's logs.
+pub trait CaptureBackend: Send + Sync {
+  // Returns the area of the screen to capture for the given capture mode
+  fn resolve_capture_geometry(&self, capture_mode: &CaptureMode) -> Option<CaptureGeometry>;
+
+  // Starts recording the video into segments. This is called on the blocking thread pool, so
+  // it may wait for the user (e.g. to pick what to share).
+  fn start_video(
+    &self,
+    segment_dir: &str,
+    capture_mode: &CaptureMode,
+    capture_geometry: &CaptureGeometry,
+    encoding_profile: &EncodingProfile,
+  ) -> Result<FfmpegSegmentedStream, KaptError>;
+
+  // Starts recording the audio into segments
+  fn start_audio(
+    &self,
+    segment_dir: &str,
+    audio_source: usize,
+  ) -> Result<FfmpegSegmentedStream, KaptError> {
+    start_segmented_audio(segment_dir, pulse_input_args(audio_source))
+  }
+
+  // Whether the video stream can be restarted to follow a window between segments
+  fn can_follow_windows(&self) -> bool {
+    false
+  }
+}
+
+// Records the X server with x11grab and the audio with PulseAudio
+pub struct X11Backend;
+
+impl CaptureBackend for X11Backend {
+  fn resolve_capture_geometry(&self, capture_mode: &CaptureMode) -> Option<CaptureGeometry> {
+    display::resolve_capture_geometry(capture_mode)
+  }
+
+  fn start_video(
+    &self,
+    segment_dir: &str,
+    _capture_mode: &CaptureMode,
+    capture_geometry: &CaptureGeometry,
+    encoding_profile: &EncodingProfile,
+  ) -> Result<FfmpegSegmentedStream, KaptError> {
+    let input_args = vec![
+      "-video_size".into(),
+      capture_geometry.video_size(),
+      "-framerate".into(),
+      encoding_profile.framerate.to_string(),
+      "-f".into(),
+      "x11grab".into(),
+      "-i".into(),
+      capture_geometry.input(),
+    ];
+
+    start_segmented_video(segment_dir, input_args, capture_geometry, encoding_profile)
+  }
+
+  fn can_follow_windows(&self) -> bool {
+    true
+  }
+}
+
+// Records ffmpeg's synthetic `testsrc` and `sine` sources, which don't need a display or a
+// sound server (e.g. in CI)
+pub struct TestPatternBackend;
+
+impl TestPatternBackend {
+  // Lavfi sources start at timestamp 0, so they're read in real time and stamped with the
+  // wall clock like the x11grab and pulse inputs
+  fn realtime_args() -> Vec<String> {
+    vec![
+      "-re".into(),
+      "-use_wallclock_as_timestamps".into(),
+      "1".into(),
+      "-f".into(),
+      "lavfi".into(),
+    ]
+  }
+}
+
+impl CaptureBackend for TestPatternBackend {
+  fn resolve_capture_geometry(&self, capture_mode: &CaptureMode) -> Option<CaptureGeometry> {
+    let (width, height) = match capture_mode {
+      CaptureMode::Region(region) => (region.width, region.height),
+      _ => (1280, 720),
+    };
+
+    Some(CaptureGeometry {
+      display: display::get_display_name(),
+      width,
+      height,
+      x: 0,
+      y: 0,
+    })
+  }
+
+  fn start_video(
+    &self,
+    segment_dir: &str,
+    _capture_mode: &CaptureMode,
+    capture_geometry: &CaptureGeometry,
+    encoding_profile: &EncodingProfile,
+  ) -> Result<FfmpegSegmentedStream, KaptError> {
+    let mut input_args = Self::realtime_args();
+    input_args.extend(vec![
+      "-i".into(),
+      format!(
+        "testsrc=size={}:rate={}",
+        capture_geometry.video_size(),
+        encoding_profile.framerate
+      ),
+    ]);
+
+    start_segmented_video(segment_dir, input_args, capture_geometry, encoding_profile)
+  }
+
+  fn start_audio(
+    &self,
+    segment_dir: &str,
+    _audio_source: usize,
+  ) -> Result<FfmpegSegmentedStream, KaptError> {
+    let mut input_args = Self::realtime_args();
+    input_args.extend(vec![
+      "-i".into(),
+      "sine=frequency=440:sample_rate=48000".into(),
+    ]);
+
+    start_segmented_audio(segment_dir, input_args)
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureBackendKind {
+  X11,
+  #[cfg(feature = "pipewire")]
+  PipeWire,
+  TestPattern,
+}
+
+impl CaptureBackendKind {
+  pub fn backend(&self) -> &'static dyn CaptureBackend {
+    match self {
+      CaptureBackendKind::X11 => &X11Backend,
+      #[cfg(feature = "pipewire")]
+      CaptureBackendKind::PipeWire => &PipeWireBackend,
+      CaptureBackendKind::TestPattern => &TestPatternBackend,
+    }
+  }
+
+  // The backend can be overridden with the `KAPT_CAPTURE_BACKEND` environment variable
+  // (e.g. `KAPT_CAPTURE_BACKEND=test_pattern` to run headlessly); otherwise PipeWire is used
+  // on Wayland (if the `pipewire` feature is enabled) and x11grab everywhere else
+  pub fn from_env() -> Self {
+    match env::var("KAPT_CAPTURE_BACKEND").as_deref() {
+      Ok("x11") => CaptureBackendKind::X11,
+      #[cfg(feature = "pipewire")]
+      Ok("pipe_wire") => CaptureBackendKind::PipeWire,
+      Ok("test_pattern") => CaptureBackendKind::TestPattern,
+      #[cfg(feature = "pipewire")]
+      _ if env::var_os("WAYLAND_DISPLAY").is_some() => CaptureBackendKind::PipeWire,
+      _ => CaptureBackendKind::X11,
+    }
+  }
+}
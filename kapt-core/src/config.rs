@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::CaptureBackendKind;
+use crate::display::CaptureMode;
+use crate::encoding::{EncodingProfile, KaptureFormat};
+use crate::error::KaptError;
+
+// How a `Recorder` records and where it saves its Kaptures
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecorderConfig {
+  // The currently selected audio source for recording
+  pub audio_source: usize,
+
+  // Where the video and audio get recorded from
+  pub capture_backend: CaptureBackendKind,
+
+  // The part of the screen that gets recorded
+  pub capture_mode: CaptureMode,
+
+  pub video_folder: Option<String>,
+
+  pub max_seconds_cached: u32,
+
+  // The maximum disk space the buffer may take up, in megabytes (unlimited if `None`)
+  pub max_cache_megabytes: Option<u32>,
+
+  // The settings used to encode the recording chunks
+  pub encoding_profile: EncodingProfile,
+
+  // The format Kaptures are saved in unless another one is asked for
+  pub kapture_format: KaptureFormat,
+}
+
+impl Default for RecorderConfig {
+  fn default() -> Self {
+    Self {
+      audio_source: 0,
+      capture_backend: CaptureBackendKind::from_env(),
+      capture_mode: CaptureMode::Screen,
+      video_folder: None,
+      // 5 minutes
+      max_seconds_cached: 5 * 300,
+      max_cache_megabytes: None,
+      encoding_profile: EncodingProfile::default(),
+      kapture_format: KaptureFormat::default(),
+    }
+  }
+}
+
+impl RecorderConfig {
+  // Rejects settings the recorder can't record with
+  pub fn validate(&self) -> Result<(), KaptError> {
+    if self.max_cache_megabytes == Some(0) {
+      return Err(KaptError::InvalidSettings(
+        "The maximum cache size must be positive.".to_string(),
+      ));
+    }
+
+    self
+      .encoding_profile
+      .validate()
+      .map_err(KaptError::InvalidSettings)
+  }
+}
@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+
+// The area of the X server that gets passed to x11grab
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CaptureGeometry {
+  // X display name (e.g. ":0.0")
+  pub display: String,
+  pub width: u32,
+  pub height: u32,
+  pub x: u32,
+  pub y: u32,
+}
+
+impl CaptureGeometry {
+  // The size of the recorded video; rounded down to even dimensions since most encoders
+  // reject odd ones
+  pub fn even_size(&self) -> (u32, u32) {
+    (self.width - self.width % 2, self.height - self.height % 2)
+  }
+
+  // The `-video_size` argument for x11grab
+  pub fn video_size(&self) -> String {
+    let (width, height) = self.even_size();
+    format!("{}x{}", width, height)
+  }
+
+  // The `-i` argument for x11grab, which includes the offset of the capture area
+  pub fn input(&self) -> String {
+    format!("{}+{},{}", self.display, self.x, self.y)
+  }
+}
+
+pub fn get_display_name() -> String {
+  env::var("DISPLAY").unwrap_or_else(|_| ":0.0".to_string())
+}
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::process::Command;
+
+// Detects the geometry of the whole X screen using `xrandr`
+pub fn get_screen_geometry() -> Option<CaptureGeometry> {
+  lazy_static! {
+    static ref SCREEN_SIZE_RE: Regex =
+      Regex::new(r#"current (\d+) x (\d+)"#).expect("Failed to compile regex");
+  };
+
+  let output = Command::new("xrandr").arg("--current").output().ok()?;
+  let output = String::from_utf8_lossy(&output.stdout);
+
+  let cap = SCREEN_SIZE_RE.captures(&output)?;
+  let width = cap.get(1)?.as_str().parse::<u32>().ok()?;
+  let height = cap.get(2)?.as_str().parse::<u32>().ok()?;
+
+  Some(CaptureGeometry {
+    display: get_display_name(),
+    width,
+    height,
+    x: 0,
+    y: 0,
+  })
+}
+
+// An X output (i.e. a physical monitor) as reported by `xrandr --listmonitors`
+#[derive(Serialize, Debug, Clone)]
+pub struct Monitor {
+  pub name: String,
+  pub is_primary: bool,
+  pub geometry: CaptureGeometry,
+}
+
+pub fn get_monitors() -> Vec<Monitor> {
+  lazy_static! {
+    // e.g. " 1: +HDMI-1 2560/597x1440/336+1920+0  HDMI-1"
+    static ref MONITOR_RE: Regex =
+      Regex::new(r#"^\s*\d+: \+?(\*?)(\S+) (\d+)/\d+x(\d+)/\d+\+(\d+)\+(\d+)"#)
+        .expect("Failed to compile regex");
+  };
+
+  let output = match Command::new("xrandr").arg("--listmonitors").output() {
+    Ok(output) => output,
+    Err(e) => {
+      log::error!("Failed to list monitors: {}", e);
+      return vec![];
+    }
+  };
+
+  let display = get_display_name();
+  String::from_utf8_lossy(&output.stdout)
+    .lines()
+    .filter_map(|line| {
+      let cap = MONITOR_RE.captures(line)?;
+      Some(Monitor {
+        name: cap.get(2)?.as_str().to_string(),
+        is_primary: !cap.get(1)?.as_str().is_empty(),
+        geometry: CaptureGeometry {
+          display: display.clone(),
+          width: cap.get(3)?.as_str().parse().ok()?,
+          height: cap.get(4)?.as_str().parse().ok()?,
+          x: cap.get(5)?.as_str().parse().ok()?,
+          y: cap.get(6)?.as_str().parse().ok()?,
+        },
+      })
+    })
+    .collect()
+}
+
+pub fn get_monitor_geometry(monitor_name: &str) -> Option<CaptureGeometry> {
+  get_monitors()
+    .into_iter()
+    .find(|monitor| monitor.name == monitor_name)
+    .map(|monitor| monitor.geometry)
+}
+
+// A rectangle of the screen chosen by the user
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CaptureRegion {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+impl CaptureRegion {
+  // Checks that the region is large enough to record and lies within the screen
+  pub fn validate(&self, screen_geometry: &CaptureGeometry) -> Result<(), String> {
+    // The video is rounded down to even dimensions, which would leave nothing of a single row
+    // or column
+    if self.width < 2 || self.height < 2 {
+      return Err("The capture region must be at least 2x2 pixels.".to_string());
+    }
+
+    // The values come from the frontend, so adding them up could overflow a u32
+    let right = self.x as u64 + self.width as u64;
+    let bottom = self.y as u64 + self.height as u64;
+    if right > screen_geometry.width as u64 || bottom > screen_geometry.height as u64 {
+      return Err(format!(
+        "The capture region must fit within the {}x{} screen.",
+        screen_geometry.width, screen_geometry.height
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+// Lets the user draw a region on the screen using `slop`
+pub fn select_capture_region() -> Option<CaptureRegion> {
+  let output = Command::new("slop")
+    .args(["-f", "%x %y %w %h"])
+    .output()
+    .ok()?;
+
+  // `slop` exits with an error when the selection is cancelled
+  if !output.status.success() {
+    return None;
+  }
+
+  let output = String::from_utf8_lossy(&output.stdout);
+  let mut values = output
+    .split_whitespace()
+    .map(|value| value.parse::<u32>().ok());
+
+  Some(CaptureRegion {
+    x: values.next()??,
+    y: values.next()??,
+    width: values.next()??,
+    height: values.next()??,
+  })
+}
+
+// A top-level X11 window as reported by `wmctrl -lx`
+#[derive(Serialize, Debug, Clone)]
+pub struct Window {
+  pub id: u32,
+  pub class: String,
+  pub title: String,
+}
+
+pub fn get_windows() -> Vec<Window> {
+  lazy_static! {
+    // e.g. "0x03a00003  0 Navigator.firefox  hostname Mozilla Firefox"
+    static ref WINDOW_RE: Regex =
+      Regex::new(r#"^0x([0-9a-fA-F]+)\s+-?\d+\s+(\S+)\s+\S+\s(.*)$"#)
+        .expect("Failed to compile regex");
+  };
+
+  let output = match Command::new("wmctrl").arg("-lx").output() {
+    Ok(output) => output,
+    Err(e) => {
+      log::error!("Failed to list windows: {}", e);
+      return vec![];
+    }
+  };
+
+  String::from_utf8_lossy(&output.stdout)
+    .lines()
+    .filter_map(|line| {
+      let cap = WINDOW_RE.captures(line)?;
+      Some(Window {
+        id: u32::from_str_radix(cap.get(1)?.as_str(), 16).ok()?,
+        class: cap.get(2)?.as_str().to_string(),
+        title: cap.get(3)?.as_str().trim().to_string(),
+      })
+    })
+    .collect()
+}
+
+// Returns the current geometry of a window using `xwininfo`, clipped to the screen since
+// x11grab can't capture outside of it
+pub fn get_window_geometry(window_id: u32) -> Option<CaptureGeometry> {
+  lazy_static! {
+    static ref X_RE: Regex =
+      Regex::new(r#"Absolute upper-left X:\s+(-?\d+)"#).expect("Failed to compile regex");
+    static ref Y_RE: Regex =
+      Regex::new(r#"Absolute upper-left Y:\s+(-?\d+)"#).expect("Failed to compile regex");
+    static ref WIDTH_RE: Regex = Regex::new(r#"Width:\s+(\d+)"#).expect("Failed to compile regex");
+    static ref HEIGHT_RE: Regex =
+      Regex::new(r#"Height:\s+(\d+)"#).expect("Failed to compile regex");
+  };
+
+  let output = Command::new("xwininfo")
+    .args(["-id", &window_id.to_string()])
+    .output()
+    .ok()?;
+
+  if !output.status.success() {
+    return None;
+  }
+
+  let output = String::from_utf8_lossy(&output.stdout);
+  let parse = |re: &Regex| -> Option<i64> { re.captures(&output)?.get(1)?.as_str().parse().ok() };
+
+  let (x, y, width, height) = (
+    parse(&X_RE)?,
+    parse(&Y_RE)?,
+    parse(&WIDTH_RE)?,
+    parse(&HEIGHT_RE)?,
+  );
+
+  let screen_geometry = get_screen_geometry()?;
+  let left = x.max(0);
+  let top = y.max(0);
+  let right = (x + width).min(screen_geometry.width as i64);
+  let bottom = (y + height).min(screen_geometry.height as i64);
+
+  // The window is entirely off-screen
+  if right <= left || bottom <= top {
+    return None;
+  }
+
+  Some(CaptureGeometry {
+    display: get_display_name(),
+    width: (right - left) as u32,
+    height: (bottom - top) as u32,
+    x: left as u32,
+    y: top as u32,
+  })
+}
+
+// What part of the screen gets recorded
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CaptureMode {
+  // All monitors
+  Screen,
+  // A single X output
+  Monitor { name: String },
+  // A user-chosen rectangle of the screen
+  Region(CaptureRegion),
+  // A single X11 window, whose geometry is followed between chunks
+  Window { id: u32 },
+}
+
+// Returns the area of the X server to capture for the given capture mode
+pub fn resolve_capture_geometry(capture_mode: &CaptureMode) -> Option<CaptureGeometry> {
+  match capture_mode {
+    CaptureMode::Screen => get_screen_geometry(),
+    CaptureMode::Monitor { name } => get_monitor_geometry(name),
+    CaptureMode::Region(region) => Some(CaptureGeometry {
+      display: get_display_name(),
+      width: region.width,
+      height: region.height,
+      x: region.x,
+      y: region.y,
+    }),
+    CaptureMode::Window { id } => get_window_geometry(*id),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn screen_geometry() -> CaptureGeometry {
+    CaptureGeometry {
+      display: ":0".to_string(),
+      width: 1920,
+      height: 1080,
+      x: 0,
+      y: 0,
+    }
+  }
+
+  #[test]
+  fn rejects_regions_outside_screen_without_overflowing() {
+    let region = CaptureRegion {
+      x: u32::MAX,
+      y: 0,
+      width: 100,
+      height: 100,
+    };
+
+    assert!(region.validate(&screen_geometry()).is_err());
+  }
+
+  #[test]
+  fn rejects_regions_too_small_to_record() {
+    for (width, height) in &[(0, 100), (1, 100), (100, 1)] {
+      let region = CaptureRegion {
+        x: 0,
+        y: 0,
+        width: *width,
+        height: *height,
+      };
+
+      assert!(region.validate(&screen_geometry()).is_err());
+    }
+  }
+
+  #[test]
+  fn accepts_regions_within_screen() {
+    let region = CaptureRegion {
+      x: 1820,
+      y: 980,
+      width: 100,
+      height: 100,
+    };
+
+    assert!(region.validate(&screen_geometry()).is_ok());
+  }
+}
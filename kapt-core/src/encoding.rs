@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoCodec {
+  Libx264,
+  Libx265,
+  LibvpxVp9,
+}
+
+impl VideoCodec {
+  // The name of the ffmpeg encoder
+  pub fn encoder(&self) -> &'static str {
+    match self {
+      VideoCodec::Libx264 => "libx264",
+      VideoCodec::Libx265 => "libx265",
+      VideoCodec::LibvpxVp9 => "libvpx-vp9",
+    }
+  }
+}
+
+// The settings used to encode the recording chunks
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncodingProfile {
+  pub framerate: u32,
+  pub video_codec: VideoCodec,
+  pub crf: u32,
+  // The x264/x265 preset (e.g. "ultrafast"); for VP9 this is mapped to `-deadline`
+  pub preset: String,
+  pub pixel_format: String,
+}
+
+impl EncodingProfile {
+  pub fn validate(&self) -> Result<(), String> {
+    if self.framerate == 0 {
+      return Err("The framerate must be positive.".to_string());
+    }
+
+    let max_crf = match self.video_codec {
+      VideoCodec::Libx264 | VideoCodec::Libx265 => 51,
+      VideoCodec::LibvpxVp9 => 63,
+    };
+
+    if self.crf > max_crf {
+      return Err(format!(
+        "The CRF for {} must be at most {}.",
+        self.video_codec.encoder(),
+        max_crf
+      ));
+    }
+
+    Ok(())
+  }
+
+  // The ffmpeg output arguments for the video encoder
+  pub fn video_args(&self) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+      "-c:v".into(),
+      self.video_codec.encoder().into(),
+      "-crf".into(),
+      self.crf.to_string(),
+      "-pix_fmt".into(),
+      self.pixel_format.clone(),
+    ];
+
+    match self.video_codec {
+      VideoCodec::Libx264 | VideoCodec::Libx265 => {
+        args.extend(vec!["-preset".into(), self.preset.clone()]);
+      }
+      VideoCodec::LibvpxVp9 => {
+        let deadline = match self.preset.as_str() {
+          "ultrafast" | "superfast" | "veryfast" | "faster" | "fast" => "realtime",
+          "slow" | "slower" | "veryslow" | "placebo" => "best",
+          _ => "good",
+        };
+
+        // VP9 only uses constant quality mode when the bitrate is 0
+        args.extend(vec![
+          "-b:v".into(),
+          "0".into(),
+          "-deadline".into(),
+          deadline.into(),
+        ]);
+      }
+    }
+
+    args
+  }
+}
+
+impl Default for EncodingProfile {
+  fn default() -> Self {
+    get_encoding_preset("balanced").expect("Missing balanced encoding preset")
+  }
+}
+
+pub const ENCODING_PRESET_NAMES: [&str; 3] = ["low_cpu", "balanced", "archival"];
+
+pub fn get_encoding_preset(name: &str) -> Option<EncodingProfile> {
+  let profile = match name {
+    "low_cpu" => EncodingProfile {
+      framerate: 15,
+      video_codec: VideoCodec::Libx264,
+      crf: 30,
+      preset: "ultrafast".to_string(),
+      pixel_format: "yuv420p".to_string(),
+    },
+    "balanced" => EncodingProfile {
+      framerate: 25,
+      video_codec: VideoCodec::Libx264,
+      crf: 23,
+      preset: "veryfast".to_string(),
+      pixel_format: "yuv420p".to_string(),
+    },
+    "archival" => EncodingProfile {
+      framerate: 30,
+      video_codec: VideoCodec::Libx265,
+      crf: 20,
+      preset: "medium".to_string(),
+      pixel_format: "yuv420p".to_string(),
+    },
+    _ => return None,
+  };
+
+  Some(profile)
+}
+
+// The longest Kapture that can be saved as an animated image
+pub const MAX_ANIMATION_SECONDS: u32 = 30;
+
+// The container and codecs a Kapture is saved in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KaptureFormat {
+  #[default]
+  Mp4,
+  Mkv,
+  // VP9 and Opus
+  Webm,
+  // Animated images without audio, for short clips
+  Gif,
+  Webp,
+  // Only the audio
+  Opus,
+  Mp3,
+  Wav,
+}
+
+pub const KAPTURE_FORMATS: [KaptureFormat; 8] = [
+  KaptureFormat::Mp4,
+  KaptureFormat::Mkv,
+  KaptureFormat::Webm,
+  KaptureFormat::Gif,
+  KaptureFormat::Webp,
+  KaptureFormat::Opus,
+  KaptureFormat::Mp3,
+  KaptureFormat::Wav,
+];
+
+impl KaptureFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      KaptureFormat::Mp4 => "mp4",
+      KaptureFormat::Mkv => "mkv",
+      KaptureFormat::Webm => "webm",
+      KaptureFormat::Gif => "gif",
+      KaptureFormat::Webp => "webp",
+      KaptureFormat::Opus => "opus",
+      KaptureFormat::Mp3 => "mp3",
+      KaptureFormat::Wav => "wav",
+    }
+  }
+
+  pub fn has_video(&self) -> bool {
+    !matches!(
+      self,
+      KaptureFormat::Opus | KaptureFormat::Mp3 | KaptureFormat::Wav
+    )
+  }
+
+  // Ffmpeg can't decode animated WebP, so previews can't be made from those Kaptures
+  pub fn has_previews(&self) -> bool {
+    self.has_video() && *self != KaptureFormat::Webp
+  }
+
+  pub fn has_audio(&self) -> bool {
+    !matches!(self, KaptureFormat::Gif | KaptureFormat::Webp)
+  }
+
+  // Animated images get large quickly, so they're only made from short clips
+  pub fn max_seconds(&self) -> Option<u32> {
+    match self {
+      KaptureFormat::Gif | KaptureFormat::Webp => Some(MAX_ANIMATION_SECONDS),
+      _ => None,
+    }
+  }
+
+  // Adds the filters the format needs after `scale_filter`, which brings every segment to the
+  // same size
+  pub fn video_filter(&self, scale_filter: Option<String>) -> Option<String> {
+    let format_filter = match self {
+      KaptureFormat::Gif => Some(
+        "fps=15,scale='min(iw,640)':-2:flags=lanczos,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse",
+      ),
+      KaptureFormat::Webp => Some("fps=15,scale='min(iw,640)':-2:flags=lanczos"),
+      _ => None,
+    };
+
+    match (scale_filter, format_filter) {
+      (Some(scale_filter), Some(format_filter)) => {
+        Some(format!("{},{}", scale_filter, format_filter))
+      }
+      (Some(scale_filter), None) => Some(scale_filter),
+      (None, Some(format_filter)) => Some(format_filter.to_string()),
+      (None, None) => None,
+    }
+  }
+
+  // The ffmpeg output arguments for the encoders
+  pub fn output_args(&self) -> Vec<String> {
+    let options: &[(&str, &str)] = match self {
+      KaptureFormat::Mp4 => &[
+        ("-c:v", "libx264"),
+        ("-preset", "veryfast"),
+        ("-crf", "23"),
+        ("-pix_fmt", "yuv420p"),
+        ("-c:a", "aac"),
+        ("-b:a", "160k"),
+        ("-movflags", "+faststart"),
+      ],
+      KaptureFormat::Mkv => &[
+        ("-c:v", "libx264"),
+        ("-preset", "veryfast"),
+        ("-crf", "23"),
+        ("-pix_fmt", "yuv420p"),
+        ("-c:a", "libopus"),
+        ("-b:a", "128k"),
+      ],
+      // VP9 only uses constant quality mode when the bitrate is 0
+      KaptureFormat::Webm => &[
+        ("-c:v", "libvpx-vp9"),
+        ("-b:v", "0"),
+        ("-crf", "32"),
+        ("-deadline", "realtime"),
+        ("-cpu-used", "8"),
+        ("-row-mt", "1"),
+        ("-pix_fmt", "yuv420p"),
+        ("-c:a", "libopus"),
+        ("-b:a", "128k"),
+      ],
+      KaptureFormat::Gif => &[("-loop", "0")],
+      KaptureFormat::Webp => &[("-c:v", "libwebp"), ("-quality", "75"), ("-loop", "0")],
+      KaptureFormat::Opus => &[("-c:a", "libopus"), ("-b:a", "128k")],
+      KaptureFormat::Mp3 => &[("-c:a", "libmp3lame"), ("-q:a", "2")],
+      KaptureFormat::Wav => &[("-c:a", "pcm_s16le")],
+    };
+
+    options
+      .iter()
+      .flat_map(|(option, value)| vec![option.to_string(), value.to_string()])
+      .collect()
+  }
+}
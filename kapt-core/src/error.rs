@@ -0,0 +1,122 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::io;
+use std::sync::PoisonError;
+
+#[derive(Debug, Clone)]
+pub enum KaptError {
+  // The Kapture can't be saved before the user has picked a folder
+  VideoFolderNotSet,
+  NotActive,
+  // Nothing has been recorded yet for the requested range
+  NoSegments,
+  CaptureGeometryUnavailable,
+  // The Kapture was cancelled before it was finished
+  KaptureCancelled,
+  // No Kapture with the ID is in the library
+  KaptureNotFound(String),
+  // A settings value sent by the frontend was rejected
+  InvalidSettings(String),
+  // The range to export is empty or isn't buffered anymore
+  InvalidRange(String),
+  // An external program (e.g. ffmpeg) couldn't be started
+  ProcessSpawn {
+    program: String,
+    message: String,
+  },
+  // An external program exited unsuccessfully
+  ProcessFailed {
+    program: String,
+    message: String,
+  },
+  // A global shortcut is bound twice or already taken by another application
+  HotkeyConflict {
+    accelerator: String,
+    message: String,
+  },
+  ScreenCast(String),
+  Io(String),
+  // Another thread panicked while holding the state lock
+  LockPoisoned,
+}
+
+impl KaptError {
+  // A stable identifier of the error for the frontend
+  pub fn kind(&self) -> &'static str {
+    match self {
+      KaptError::VideoFolderNotSet => "video_folder_not_set",
+      KaptError::NotActive => "not_active",
+      KaptError::NoSegments => "no_segments",
+      KaptError::CaptureGeometryUnavailable => "capture_geometry_unavailable",
+      KaptError::KaptureCancelled => "kapture_cancelled",
+      KaptError::KaptureNotFound(_) => "kapture_not_found",
+      KaptError::InvalidSettings(_) => "invalid_settings",
+      KaptError::InvalidRange(_) => "invalid_range",
+      KaptError::ProcessSpawn { .. } => "process_spawn",
+      KaptError::ProcessFailed { .. } => "process_failed",
+      KaptError::HotkeyConflict { .. } => "hotkey_conflict",
+      KaptError::ScreenCast(_) => "screen_cast",
+      KaptError::Io(_) => "io",
+      KaptError::LockPoisoned => "lock_poisoned",
+    }
+  }
+
+  pub fn process_spawn(program: &str, error: impl fmt::Display) -> Self {
+    KaptError::ProcessSpawn {
+      program: program.to_string(),
+      message: error.to_string(),
+    }
+  }
+}
+
+impl fmt::Display for KaptError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      KaptError::VideoFolderNotSet => write!(f, "No video folder has been selected."),
+      KaptError::NotActive => write!(f, "Kapt isn't currently active."),
+      KaptError::NoSegments => write!(f, "Nothing has been recorded for the Kapture yet."),
+      KaptError::CaptureGeometryUnavailable => write!(f, "Failed to detect the capture geometry."),
+      KaptError::KaptureCancelled => write!(f, "The Kapture was cancelled."),
+      KaptError::KaptureNotFound(kapture_id) => {
+        write!(f, "The Kapture {} isn't in the library.", kapture_id)
+      }
+      KaptError::InvalidSettings(message) => write!(f, "{}", message),
+      KaptError::InvalidRange(message) => write!(f, "{}", message),
+      KaptError::ProcessSpawn { program, message } => {
+        write!(f, "Failed to start {}: {}", program, message)
+      }
+      KaptError::ProcessFailed { program, message } => write!(f, "{} failed: {}", program, message),
+      KaptError::HotkeyConflict {
+        accelerator,
+        message,
+      } => write!(f, "Failed to bind {}: {}", accelerator, message),
+      KaptError::ScreenCast(message) => write!(f, "Failed to start the screen cast: {}", message),
+      KaptError::Io(message) => write!(f, "{}", message),
+      KaptError::LockPoisoned => write!(f, "The Kapt state is poisoned."),
+    }
+  }
+}
+
+impl std::error::Error for KaptError {}
+
+// The frontend receives errors as `{ kind, message }`
+impl Serialize for KaptError {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut error = serializer.serialize_struct("KaptError", 2)?;
+    error.serialize_field("kind", self.kind())?;
+    error.serialize_field("message", &self.to_string())?;
+    error.end()
+  }
+}
+
+impl From<io::Error> for KaptError {
+  fn from(error: io::Error) -> Self {
+    KaptError::Io(error.to_string())
+  }
+}
+
+impl<T> From<PoisonError<T>> for KaptError {
+  fn from(_: PoisonError<T>) -> Self {
+    KaptError::LockPoisoned
+  }
+}
@@ -0,0 +1,39 @@
+use serde::Serialize;
+
+use crate::error::KaptError;
+use crate::library::KaptureEntry;
+use crate::markers::Marker;
+
+// The steps of assembling a Kapture
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KaptureStage {
+  // Ffmpeg is still recording the segments at the end of the Kapture
+  WaitingForSegments,
+  // The segments are being concatenated and trimmed
+  Assembling,
+  // The Kapture was created, failed or was cancelled
+  Finished,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct KaptureProgress {
+  // Identifies the Kapture for `Recorder::cancel_kapture`
+  pub kapture_id: String,
+  pub stage: KaptureStage,
+  // How much of the current stage is done, from 0 to 100
+  pub percent: u32,
+}
+
+// Something that happened in the background which the clients of a `Recorder` (the window,
+// the tray or the daemon's log) might want to show
+#[derive(Debug, Clone)]
+pub enum KaptEvent {
+  ActivationToggled(bool),
+  KaptureProgress(KaptureProgress),
+  // Has the paths of the video and its previews
+  KaptureCreated(Box<KaptureEntry>),
+  // A marker was added or removed, or left the buffer; has all the current markers
+  MarkersChanged(Vec<Marker>),
+  Error(KaptError),
+}
@@ -0,0 +1,206 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const MICROS_PER_SECOND: u128 = 1_000_000;
+
+// A duration or timestamp as ffmpeg reads and writes it, with microsecond precision like
+// ffmpeg's own timestamps. It's written as `HH:MM:SS.mmmmmm` and can be parsed from either of
+// ffmpeg's time duration forms, `[HH:]MM:SS[.m...]` and `S+[.m...][s|ms|us]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FfmpegDuration(Duration);
+
+impl FfmpegDuration {
+  pub fn from_millis(milliseconds: u128) -> Self {
+    Self::from_micros(milliseconds.saturating_mul(1000))
+  }
+
+  pub fn from_micros(microseconds: u128) -> Self {
+    let seconds = u64::try_from(microseconds / MICROS_PER_SECOND).unwrap_or(u64::MAX);
+    let microseconds = (microseconds % MICROS_PER_SECOND) as u32;
+
+    Self(Duration::new(seconds, microseconds * 1000))
+  }
+
+  pub fn as_millis(&self) -> u128 {
+    self.0.as_millis()
+  }
+
+  pub fn as_micros(&self) -> u128 {
+    self.0.as_micros()
+  }
+
+  pub fn as_duration(&self) -> Duration {
+    self.0
+  }
+}
+
+impl From<Duration> for FfmpegDuration {
+  // Anything finer than a microsecond is dropped
+  fn from(duration: Duration) -> Self {
+    Self::from_micros(duration.as_micros())
+  }
+}
+
+impl fmt::Display for FfmpegDuration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let seconds = self.0.as_secs();
+
+    write!(
+      f,
+      "{:0>2}:{:0>2}:{:0>2}.{:0>6}",
+      seconds / 3600,
+      seconds / 60 % 60,
+      seconds % 60,
+      self.0.subsec_micros()
+    )
+  }
+}
+
+// Parses the digits of a whole number of units
+fn parse_whole(digits: &str) -> Result<u128, String> {
+  if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+    return Err(format!("\"{}\" isn't a number.", digits));
+  }
+
+  digits.parse::<u128>().map_err(|e| e.to_string())
+}
+
+// Parses `S+[.m...]` into microseconds, where `S` is a number of units of `unit_micros`
+// microseconds each. Digits beyond a microsecond are dropped.
+fn parse_decimal(decimal: &str, unit_micros: u128) -> Result<u128, String> {
+  let (whole, fraction) = match decimal.find('.') {
+    Some(i) => (&decimal[..i], &decimal[i + 1..]),
+    None => (decimal, ""),
+  };
+
+  let whole = parse_whole(whole)?;
+
+  if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+    return Err(format!("\"{}\" isn't a number.", decimal));
+  }
+
+  // The fraction in millionths of a unit
+  let fraction_digits: String = fraction.chars().take(6).collect();
+  let fraction = parse_whole(&format!("{:0<6}", fraction_digits))?;
+
+  Ok(whole * unit_micros + fraction * unit_micros / MICROS_PER_SECOND)
+}
+
+impl FromStr for FfmpegDuration {
+  type Err = String;
+
+  fn from_str(time: &str) -> Result<Self, Self::Err> {
+    let time = time.trim();
+
+    if time.starts_with('-') {
+      return Err(format!("\"{}\" is negative.", time));
+    }
+
+    // `[HH:]MM:SS[.m...]`
+    if time.contains(':') {
+      let parts: Vec<&str> = time.split(':').collect();
+      let (hours, minutes, seconds) = match parts.as_slice() {
+        [minutes, seconds] => ("0", *minutes, *seconds),
+        [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+        _ => return Err(format!("\"{}\" isn't a valid time.", time)),
+      };
+
+      let hours = parse_whole(hours)?;
+      let minutes = parse_whole(minutes)?;
+      let seconds = parse_decimal(seconds, MICROS_PER_SECOND)?;
+
+      if minutes >= 60 || seconds >= 60 * MICROS_PER_SECOND {
+        return Err(format!(
+          "The minutes and seconds of \"{}\" must be less than 60.",
+          time
+        ));
+      }
+
+      return Ok(Self::from_micros(
+        (hours * 3600 + minutes * 60) * MICROS_PER_SECOND + seconds,
+      ));
+    }
+
+    // `S+[.m...][s|ms|us]`
+    let (decimal, unit_micros) = if let Some(decimal) = time.strip_suffix("ms") {
+      (decimal, 1000)
+    } else if let Some(decimal) = time.strip_suffix("us") {
+      (decimal, 1)
+    } else if let Some(decimal) = time.strip_suffix('s') {
+      (decimal, MICROS_PER_SECOND)
+    } else {
+      (time, MICROS_PER_SECOND)
+    };
+
+    Ok(Self::from_micros(parse_decimal(decimal, unit_micros)?))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(time: &str) -> Result<FfmpegDuration, String> {
+    time.parse::<FfmpegDuration>()
+  }
+
+  #[test]
+  fn formats_hours() {
+    assert_eq!(
+      FfmpegDuration::from_millis(3_723_500).to_string(),
+      "01:02:03.500000"
+    );
+  }
+
+  #[test]
+  fn round_trips_long_and_precise_durations() {
+    for microseconds in &[
+      1,
+      999_999,
+      3_600_000_001,
+      // The longest buffer that can be configured is far shorter than this
+      100 * 3_600_000_000 + 59 * 60_000_000 + 59_999_999,
+    ] {
+      let duration = FfmpegDuration::from_micros(*microseconds);
+
+      assert_eq!(parse(&duration.to_string()), Ok(duration));
+    }
+  }
+
+  #[test]
+  fn parses_fractions_of_any_length() {
+    assert_eq!(parse("1.627"), Ok(FfmpegDuration::from_micros(1_627_000)));
+    assert_eq!(
+      parse("1.627318"),
+      Ok(FfmpegDuration::from_micros(1_627_318))
+    );
+    // Digits beyond a microsecond are dropped
+    assert_eq!(
+      parse("1.627318999"),
+      Ok(FfmpegDuration::from_micros(1_627_318))
+    );
+    assert_eq!(parse("00:01.5"), Ok(FfmpegDuration::from_millis(1_500)));
+    assert_eq!(parse("1"), Ok(FfmpegDuration::from_millis(1_000)));
+  }
+
+  #[test]
+  fn parses_units() {
+    assert_eq!(parse("2s"), Ok(FfmpegDuration::from_millis(2_000)));
+    assert_eq!(parse("1500ms"), Ok(FfmpegDuration::from_millis(1_500)));
+    assert_eq!(parse("1.5ms"), Ok(FfmpegDuration::from_micros(1_500)));
+    assert_eq!(parse("250us"), Ok(FfmpegDuration::from_micros(250)));
+  }
+
+  #[test]
+  fn rejects_invalid_times() {
+    assert!(parse("00:60").is_err());
+    assert!(parse("01:00:60").is_err());
+    assert!(parse("-1").is_err());
+    assert!(parse("-00:01").is_err());
+    assert!(parse("1:2:3:4").is_err());
+    assert!(parse("1.2.3").is_err());
+    assert!(parse("").is_err());
+  }
+}
@@ -0,0 +1,443 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+
+use crate::backend::SEGMENT_SECONDS;
+use crate::encoding::KaptureFormat;
+use crate::error::KaptError;
+use crate::events::{KaptEvent, KaptureProgress, KaptureStage};
+use crate::ffmpeg_time::FfmpegDuration;
+use crate::library::KaptureEntry;
+use crate::previews;
+use crate::recorder::Recorder;
+use crate::recording;
+use crate::state::FfmpegSegment;
+use crate::timeline::{self, KapturePlan, PlannedSegment};
+use crate::utils::create_temp_path;
+use crate::utils::get_current_time;
+use crate::utils::get_file_date_string;
+use nanoid::nanoid;
+
+// A Kapture that's being made, which can be cancelled from another task
+pub struct KaptureJob {
+  pub id: String,
+  cancelled: AtomicBool,
+  // Wakes up the ffmpeg process assembling the Kapture so that it can be killed
+  cancel_notify: Notify,
+}
+
+impl KaptureJob {
+  pub fn new() -> Self {
+    Self {
+      id: nanoid!(),
+      cancelled: AtomicBool::new(false),
+      cancel_notify: Notify::new(),
+    }
+  }
+
+  // Stops the Kapture at its current stage; its temporary files are removed by the task
+  // making it
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+    // Stored until ffmpeg is started if the Kapture is still waiting for segments
+    self.cancel_notify.notify_one();
+  }
+
+  fn check_cancelled(&self) -> Result<(), KaptError> {
+    if self.cancelled.load(Ordering::SeqCst) {
+      Err(KaptError::KaptureCancelled)
+    } else {
+      Ok(())
+    }
+  }
+
+  fn publish_progress(&self, recorder: &Recorder, stage: KaptureStage, percent: u32) {
+    recorder.publish_event(KaptEvent::KaptureProgress(KaptureProgress {
+      kapture_id: self.id.clone(),
+      stage,
+      percent: percent.min(100),
+    }));
+  }
+
+  // Runs an ffmpeg command that writes its progress to stdout (`-progress pipe:1`), reporting
+  // how much of `duration` it has written. Ffmpeg is killed if the Kapture is cancelled.
+  async fn run_ffmpeg(
+    &self,
+    recorder: &Recorder,
+    mut command: Command,
+    duration: FfmpegDuration,
+  ) -> Result<ExitStatus, KaptError> {
+    let mut child = command
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .kill_on_drop(true)
+      .spawn()
+      .map_err(|e| KaptError::process_spawn("ffmpeg", e))?;
+
+    if let Some(stdout) = child.stdout.take() {
+      let mut lines = BufReader::new(stdout).lines();
+
+      // Ffmpeg reports the timestamp it's at as `out_time_us=...` about twice a second
+      let cancelled = loop {
+        tokio::select! {
+          line = lines.next_line() => match line {
+            Ok(Some(line)) => {
+              if let Some(out_time) = line
+                .strip_prefix("out_time_us=")
+                .and_then(|out_time| out_time.parse::<u128>().ok())
+              {
+                let percent = out_time * 100 / duration.as_micros().max(1);
+                self.publish_progress(recorder, KaptureStage::Assembling, percent as u32);
+              }
+            }
+            // Ffmpeg has closed its output, so it's about to exit
+            _ => break false,
+          },
+          _ = self.cancel_notify.notified() => break true,
+        }
+      };
+
+      if cancelled {
+        if let Err(e) = child.start_kill() {
+          log::error!("Failed to stop ffmpeg process: {}", e);
+        }
+      }
+    }
+
+    Ok(child.wait().await?)
+  }
+}
+
+// Returns the segments that overlap the range from `start_time` to `end_time`, oldest first
+fn pick_segments(
+  segments: &VecDeque<Arc<FfmpegSegment>>,
+  start_time: u128,
+  end_time: u128,
+) -> Vec<Arc<FfmpegSegment>> {
+  let mut picked_segments: Vec<Arc<FfmpegSegment>> = segments
+    .iter()
+    .filter(|segment| segment.end_time > start_time && segment.start_time < end_time)
+    .cloned()
+    .collect();
+
+  picked_segments.sort_by_key(|segment| segment.start_time);
+  picked_segments
+}
+
+// Writes a list of the segments for ffmpeg's concat demuxer and returns its path
+fn write_segment_list(segments: &[PlannedSegment]) -> Result<String, KaptError> {
+  let mut segment_path_list = String::new();
+  for planned_segment in segments {
+    segment_path_list.push_str(&format!("file '{}'\n", planned_segment.segment.path));
+
+    if let Some(outpoint) = planned_segment.outpoint() {
+      segment_path_list.push_str(&format!(
+        "outpoint {}\n",
+        FfmpegDuration::from_millis(outpoint)
+      ));
+    }
+  }
+
+  let segment_list_path = create_temp_path(&format!("{}.txt", nanoid!()));
+  fs::write(&segment_list_path, segment_path_list)?;
+
+  Ok(segment_list_path)
+}
+
+// Concatenates the segments of the plan and trims them to the Kapture
+// Returns path of the final recording
+async fn concat_segments(
+  recorder: &Recorder,
+  job: &KaptureJob,
+  plan: &KapturePlan,
+  format: KaptureFormat,
+  video_dir_path: &str,
+) -> Result<String, KaptError> {
+  log::debug!("Kapture plan: {:?}", plan);
+
+  let last_video_segment = plan.video.segments.last().ok_or(KaptError::NoSegments)?;
+
+  let video_offset = FfmpegDuration::from_millis(plan.video.offset);
+  let audio_offset = FfmpegDuration::from_millis(plan.audio.offset);
+  let duration = FfmpegDuration::from_millis(plan.duration);
+
+  log::debug!(
+    "Video offset: {}, audio offset: {}, duration: {}",
+    video_offset,
+    audio_offset,
+    duration
+  );
+
+  // Every segment is scaled to the size of the most recent one, since the recorded area can
+  // change between segments when following a window. The size is unknown (0x0) when the
+  // backend couldn't report it, in which case the segments are left as they are.
+  let scale_filter = last_video_segment
+    .segment
+    .capture_geometry
+    .as_ref()
+    .map(|capture_geometry| capture_geometry.even_size())
+    .filter(|(width, height)| *width > 0 && *height > 0)
+    .map(|(width, height)| {
+      format!(
+        "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2",
+        w = width,
+        h = height
+      )
+    });
+
+  let video_segment_list_path = write_segment_list(&plan.video.segments)?;
+  let audio_segment_list_path = write_segment_list(&plan.audio.segments)?;
+
+  let file_date_string = get_file_date_string();
+  let final_video_path = Path::new(video_dir_path)
+    .join(format!(
+      "Kapt-{}-{}.{}",
+      file_date_string,
+      nanoid!(4),
+      format.extension()
+    ))
+    .to_string_lossy()
+    .to_string();
+
+  let mut command = Command::new("ffmpeg");
+
+  command
+    .args(["-ss", &video_offset.to_string()])
+    .args(["-f", "concat"])
+    .args(["-safe", "0"])
+    .args(["-i", &video_segment_list_path])
+    .args(["-ss", &audio_offset.to_string()])
+    .args(["-f", "concat"])
+    .args(["-safe", "0"])
+    .args(["-i", &audio_segment_list_path])
+    .args(["-t", &duration.to_string()])
+    .args(["-progress", "pipe:1", "-nostats"]);
+
+  // Audio-only formats have no video to filter
+  if format.has_video() {
+    command.args(["-map", "0:v:0"]);
+
+    if let Some(video_filter) = format.video_filter(scale_filter) {
+      command.args(["-vf", &video_filter]);
+    }
+  }
+
+  if format.has_audio() {
+    command.args(["-map", "1:a:0"]);
+  }
+
+  command.args(format.output_args());
+
+  command.args(["-y"]).args([&final_video_path]);
+
+  job.publish_progress(recorder, KaptureStage::Assembling, 0);
+  let status = job.run_ffmpeg(recorder, command, duration).await;
+
+  for segment_list_path in &[video_segment_list_path, audio_segment_list_path] {
+    if let Err(e) = fs::remove_file(segment_list_path) {
+      log::error!("{}", e);
+    }
+  }
+
+  let status = status.and_then(|status| {
+    job.check_cancelled()?;
+    Ok(status)
+  });
+
+  let status = match status {
+    Ok(status) => status,
+    Err(e) => {
+      // Ffmpeg was stopped halfway through writing the video
+      if Path::new(&final_video_path).exists() {
+        if let Err(e) = fs::remove_file(&final_video_path) {
+          log::error!("{}", e);
+        }
+      }
+
+      return Err(e);
+    }
+  };
+
+  if !status.success() {
+    return Err(KaptError::ProcessFailed {
+      program: "ffmpeg".to_string(),
+      message: format!("Failed to assemble the Kapture ({})", status),
+    });
+  }
+
+  log::info!("Final video path: {:?}", final_video_path);
+
+  Ok(final_video_path)
+}
+
+// Waits until ffmpeg has finished the segments that cover `end_time`, since the end of a
+// Kapture usually falls into the segments that are still being recorded. `end_time` can be in
+// the future, in which case the recording keeps going until then.
+async fn wait_for_segments(
+  recorder: &Recorder,
+  job: &KaptureJob,
+  end_time: u128,
+) -> Result<(), KaptError> {
+  let state_lock = recorder.state();
+
+  let wait_start_time = get_current_time();
+  // A segment can end up slightly longer than `SEGMENT_SECONDS` since it's split on keyframes
+  let segment_milliseconds = SEGMENT_SECONDS as u128 * 1000;
+  let deadline = end_time
+    .max(wait_start_time)
+    .saturating_add(segment_milliseconds * 2);
+  // How long the wait should take, for the progress
+  let expected_wait = end_time.saturating_sub(wait_start_time) + segment_milliseconds;
+
+  let mut last_percent = 0;
+  loop {
+    job.check_cancelled()?;
+    recording::collect_segments(state_lock)?;
+
+    let percent = get_current_time().saturating_sub(wait_start_time) * 100 / expected_wait;
+    let percent = percent.min(100) as u32;
+    if percent != last_percent {
+      job.publish_progress(recorder, KaptureStage::WaitingForSegments, percent);
+      last_percent = percent;
+    }
+
+    let segments_finished = {
+      let state = state_lock.read()?;
+
+      // The buffer is cleared when Kapt is deactivated, so there's nothing left to wait for
+      if !state.is_active() {
+        return Err(KaptError::NotActive);
+      }
+
+      let covers_end_time = |segments: &VecDeque<Arc<FfmpegSegment>>| matches!(segments.back(), Some(segment) if segment.end_time >= end_time);
+
+      covers_end_time(&state.video_segments) && covers_end_time(&state.audio_segments)
+    };
+
+    if segments_finished || get_current_time() > deadline {
+      return Ok(());
+    }
+
+    sleep(Duration::from_millis(250)).await;
+  }
+}
+
+// Returns the library entry of the final recording
+pub async fn process_kapture(
+  recorder: &Recorder,
+  job: &KaptureJob,
+  timestamp: u128,
+  duration: u128,
+  format: KaptureFormat,
+) -> Result<KaptureEntry, KaptError> {
+  let state_lock = recorder.state();
+
+  if let Some(max_seconds) = format.max_seconds() {
+    if duration > max_seconds as u128 * 1000 {
+      return Err(KaptError::InvalidSettings(format!(
+        "A {} Kapture can be at most {} seconds long.",
+        format.extension().to_uppercase(),
+        max_seconds
+      )));
+    }
+  }
+
+  {
+    let state = state_lock.read()?;
+
+    if !state.is_active() {
+      return Err(KaptError::NotActive);
+    }
+
+    if state.config.video_folder.is_none() {
+      return Err(KaptError::VideoFolderNotSet);
+    }
+  }
+
+  job.publish_progress(recorder, KaptureStage::WaitingForSegments, 0);
+  wait_for_segments(recorder, job, timestamp).await?;
+
+  let start_time = timestamp.saturating_sub(duration);
+
+  // The recording keeps going while the Kapture is assembled; the picked segments are shared
+  // with the buffer so that their files aren't removed when they get evicted
+  let (video_segments, audio_segments, video_dir_path, audio_source) = {
+    let state = state_lock.read()?;
+
+    (
+      pick_segments(&state.video_segments, start_time, timestamp),
+      pick_segments(&state.audio_segments, start_time, timestamp),
+      state
+        .config
+        .video_folder
+        .clone()
+        .ok_or(KaptError::VideoFolderNotSet)?,
+      state.config.audio_source,
+    )
+  };
+
+  let plan = timeline::plan_kapture(&video_segments, &audio_segments, start_time, timestamp)?;
+  let kapture_path = concat_segments(recorder, job, &plan, format, &video_dir_path).await?;
+
+  // The last segment has the area that was recorded at the end of the Kapture
+  let capture_geometry = plan
+    .video
+    .segments
+    .last()
+    .and_then(|planned| planned.segment.capture_geometry.clone());
+
+  let mut kapture = KaptureEntry::new(
+    kapture_path,
+    format,
+    get_current_time(),
+    duration,
+    plan.duration,
+    audio_source,
+    capture_geometry,
+  );
+
+  // The Kapture itself has been saved, so missing previews only get reported. Cancelling
+  // drops the previews' ffmpeg processes, which kills them.
+  if format.has_previews() {
+    tokio::select! {
+      previews = previews::create_previews(&kapture.path, plan.duration) => match previews {
+        Ok(previews) => kapture.previews = Some(previews),
+        Err(e) => recorder.report_error(e),
+      },
+      _ = job.cancel_notify.notified() => {}
+    }
+  }
+
+  if let Err(e) = job.check_cancelled() {
+    previews::remove_previews_of(&kapture.path);
+    if let Err(e) = fs::remove_file(&kapture.path) {
+      log::error!("{}", e);
+    }
+
+    return Err(e);
+  }
+
+  Ok(kapture)
+}
+
+// timestamp - Unix timestamp of when the user pressed the Kapture button (in milliseconds)
+// duration - The length of the Kapture (in milliseconds)
+pub async fn create_kapture(
+  recorder: &Recorder,
+  job: &KaptureJob,
+  timestamp: u128,
+  duration: u128,
+  format: KaptureFormat,
+) -> Result<KaptureEntry, KaptError> {
+  let kapture = process_kapture(recorder, job, timestamp, duration, format).await;
+  job.publish_progress(recorder, KaptureStage::Finished, 100);
+
+  kapture
+}
@@ -0,0 +1,26 @@
+// The recording core of Kapt: keeps a rolling buffer of the screen and audio and assembles
+// Kaptures from it. The Tauri app and the daemon are both thin wrappers around `Recorder`.
+pub mod audio;
+pub mod backend;
+pub mod config;
+pub mod display;
+pub mod encoding;
+pub mod error;
+pub mod events;
+pub mod ffmpeg_time;
+mod kapture;
+pub mod library;
+pub mod markers;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+pub mod previews;
+pub mod recorder;
+pub mod recording;
+pub mod state;
+pub mod timeline;
+pub mod utils;
+
+pub use config::RecorderConfig;
+pub use error::KaptError;
+pub use events::KaptEvent;
+pub use recorder::Recorder;
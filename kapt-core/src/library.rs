@@ -0,0 +1,243 @@
+// An index of every Kapture that has been made, kept as one line of JSON per Kapture so that
+// a new Kapture only has to be appended
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::display::CaptureGeometry;
+use crate::encoding::KaptureFormat;
+use crate::error::KaptError;
+use crate::previews::{self, KapturePreviews};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KaptureEntry {
+  pub id: String,
+  pub path: String,
+  pub format: KaptureFormat,
+  // The unix timestamp (in milliseconds) at which the Kapture was saved
+  pub created_at: u128,
+  // The length that was asked for and the length of the video, which is shorter when less
+  // was buffered (in milliseconds)
+  pub requested_milliseconds: u128,
+  pub actual_milliseconds: u128,
+  pub audio_source: usize,
+  pub capture_geometry: Option<CaptureGeometry>,
+  // The size of the file in bytes
+  pub file_size: u64,
+  // Missing for Kaptures without video or if they couldn't be made
+  #[serde(default)]
+  pub previews: Option<KapturePreviews>,
+  #[serde(default)]
+  pub title: Option<String>,
+  #[serde(default)]
+  pub tags: Vec<String>,
+}
+
+impl KaptureEntry {
+  pub fn new(
+    path: String,
+    format: KaptureFormat,
+    created_at: u128,
+    requested_milliseconds: u128,
+    actual_milliseconds: u128,
+    audio_source: usize,
+    capture_geometry: Option<CaptureGeometry>,
+  ) -> Self {
+    let file_size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+
+    Self {
+      id: nanoid!(),
+      path,
+      format,
+      created_at,
+      requested_milliseconds,
+      actual_milliseconds,
+      audio_source,
+      capture_geometry,
+      file_size,
+      previews: None,
+      title: None,
+      tags: vec![],
+    }
+  }
+
+  // Whether the title, a tag or the file name contains `query`, ignoring case
+  fn matches(&self, query: &str) -> bool {
+    let query = query.to_lowercase();
+    let file_name = Path::new(&self.path)
+      .file_name()
+      .map(|file_name| file_name.to_string_lossy().to_lowercase())
+      .unwrap_or_default();
+
+    file_name.contains(&query)
+      || matches!(&self.title, Some(title) if title.to_lowercase().contains(&query))
+      || self
+        .tags
+        .iter()
+        .any(|tag| tag.to_lowercase().contains(&query))
+  }
+}
+
+pub struct KaptureLibrary {
+  index_path: PathBuf,
+  // Held while the index is read and rewritten, so that concurrent changes aren't lost
+  index_lock: Mutex<()>,
+}
+
+impl KaptureLibrary {
+  pub fn new(index_path: PathBuf) -> Self {
+    Self {
+      index_path,
+      index_lock: Mutex::new(()),
+    }
+  }
+
+  fn read_entries(&self) -> Result<Vec<KaptureEntry>, KaptError> {
+    let index = match fs::read_to_string(&self.index_path) {
+      Ok(index) => index,
+      // Nothing has been Kaptured yet
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+      Err(e) => return Err(e.into()),
+    };
+
+    // A broken line (e.g. from a crash while appending) shouldn't hide the other Kaptures
+    Ok(
+      index
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+          Ok(entry) => Some(entry),
+          Err(e) => {
+            log::error!("Failed to read Kapture library entry: {}", e);
+            None
+          }
+        })
+        .collect(),
+    )
+  }
+
+  fn write_entries(&self, entries: &[KaptureEntry]) -> Result<(), KaptError> {
+    let mut index = String::new();
+    for entry in entries {
+      index += &serde_json::to_string(entry).map_err(|e| KaptError::Io(e.to_string()))?;
+      index.push('\n');
+    }
+
+    // Writing to a temporary file first so that a crash can't leave a truncated index behind
+    let temp_index_path = self.index_path.with_extension("jsonl.tmp");
+    fs::write(&temp_index_path, index)?;
+    fs::rename(&temp_index_path, &self.index_path)?;
+
+    Ok(())
+  }
+
+  // Changes the entry with the ID `kapture_id` and returns it
+  fn update_entry(
+    &self,
+    kapture_id: &str,
+    update: impl FnOnce(&mut KaptureEntry),
+  ) -> Result<KaptureEntry, KaptError> {
+    let _index_lock = self.index_lock.lock()?;
+
+    let mut entries = self.read_entries()?;
+    let entry = entries
+      .iter_mut()
+      .find(|entry| entry.id == kapture_id)
+      .ok_or_else(|| KaptError::KaptureNotFound(kapture_id.to_string()))?;
+    update(entry);
+    let entry = entry.clone();
+
+    self.write_entries(&entries)?;
+    Ok(entry)
+  }
+
+  pub fn add(&self, entry: &KaptureEntry) -> Result<(), KaptError> {
+    let _index_lock = self.index_lock.lock()?;
+
+    if let Some(index_dir) = self.index_path.parent() {
+      fs::create_dir_all(index_dir)?;
+    }
+
+    let mut line = serde_json::to_string(entry).map_err(|e| KaptError::Io(e.to_string()))?;
+    line.push('\n');
+
+    OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.index_path)?
+      .write_all(line.as_bytes())?;
+
+    Ok(())
+  }
+
+  // Every Kapture, newest first
+  pub fn list(&self) -> Result<Vec<KaptureEntry>, KaptError> {
+    let _index_lock = self.index_lock.lock()?;
+
+    let mut entries = self.read_entries()?;
+    entries.reverse();
+    Ok(entries)
+  }
+
+  // The Kaptures whose title, tags or file name contain `query`, newest first
+  pub fn search(&self, query: &str) -> Result<Vec<KaptureEntry>, KaptError> {
+    let query = query.trim();
+
+    Ok(
+      self
+        .list()?
+        .into_iter()
+        .filter(|entry| entry.matches(query))
+        .collect(),
+    )
+  }
+
+  // Titles are only kept in the index, so the file keeps its name
+  pub fn rename(&self, kapture_id: &str, title: Option<String>) -> Result<KaptureEntry, KaptError> {
+    let title = title
+      .map(|title| title.trim().to_string())
+      .filter(|title| !title.is_empty());
+
+    self.update_entry(kapture_id, |entry| entry.title = title)
+  }
+
+  pub fn set_tags(&self, kapture_id: &str, tags: Vec<String>) -> Result<KaptureEntry, KaptError> {
+    // Duplicates are dropped wherever they are, keeping the order the tags were given in
+    let mut seen_tags = HashSet::new();
+    let tags: Vec<String> = tags
+      .into_iter()
+      .map(|tag| tag.trim().to_string())
+      .filter(|tag| !tag.is_empty() && seen_tags.insert(tag.clone()))
+      .collect();
+
+    self.update_entry(kapture_id, |entry| entry.tags = tags)
+  }
+
+  // Removes the Kapture from the index and deletes its files
+  pub fn delete(&self, kapture_id: &str) -> Result<(), KaptError> {
+    let _index_lock = self.index_lock.lock()?;
+
+    let mut entries = self.read_entries()?;
+    let i = entries
+      .iter()
+      .position(|entry| entry.id == kapture_id)
+      .ok_or_else(|| KaptError::KaptureNotFound(kapture_id.to_string()))?;
+    let entry = entries.remove(i);
+
+    // The file might already have been deleted by hand
+    match fs::remove_file(&entry.path) {
+      Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+      _ => {}
+    }
+
+    if let Some(previews) = entry.previews.as_ref() {
+      previews::remove_previews(previews);
+    }
+
+    self.write_entries(&entries)
+  }
+}
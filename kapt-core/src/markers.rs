@@ -0,0 +1,50 @@
+// Moments the user marked in the buffer, so that a range between them can be exported later
+// instead of deciding the length of a Kapture when pressing the button
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerKind {
+  Start,
+  End,
+  Bookmark,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Marker {
+  pub id: String,
+  // The unix timestamp (in milliseconds) of the marked moment
+  pub time: u128,
+  pub kind: MarkerKind,
+  pub label: Option<String>,
+}
+
+impl Marker {
+  pub fn new(time: u128, kind: MarkerKind, label: Option<String>) -> Self {
+    Self {
+      id: nanoid!(),
+      time,
+      kind,
+      // An empty label is the same as none
+      label: label.filter(|label| !label.trim().is_empty()),
+    }
+  }
+}
+
+// Adds `marker` to `markers`, which are kept oldest first
+pub fn insert_marker(markers: &mut Vec<Marker>, marker: Marker) {
+  let i = markers
+    .iter()
+    .position(|other| other.time > marker.time)
+    .unwrap_or(markers.len());
+  markers.insert(i, marker);
+}
+
+// Removes the markers before `cache_start_time`, whose footage isn't buffered anymore. Returns
+// whether any were removed.
+pub fn evict_expired_markers(markers: &mut Vec<Marker>, cache_start_time: u128) -> bool {
+  let marker_count = markers.len();
+  markers.retain(|marker| marker.time >= cache_start_time);
+  markers.len() != marker_count
+}
@@ -0,0 +1,393 @@
+// The PipeWire capture backend, which is only built with the `pipewire` feature since it needs
+// libdbus to talk to xdg-desktop-portal
+use dbus::arg::{AppendAll, PropMap, RefArg, Variant};
+use dbus::blocking::SyncConnection;
+use dbus::message::MatchRule;
+use nanoid::nanoid;
+use std::env;
+use std::fs::File;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+
+use crate::backend::{start_segmented_video, CaptureBackend};
+use crate::display::{CaptureGeometry, CaptureMode};
+use crate::encoding::EncodingProfile;
+use crate::error::KaptError;
+use crate::state::{FfmpegSegmentedStream, StreamSource};
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREEN_CAST_INTERFACE: &str = "org.freedesktop.portal.ScreenCast";
+
+// How long to wait for a method call to the portal to return
+const PORTAL_CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long to wait for the user to answer the portal's dialog
+const PORTAL_RESPONSE_TIMEOUT: Duration = Duration::from_secs(120);
+
+// A PipeWire stream the user shared through the ScreenCast portal
+#[derive(Debug)]
+pub struct ScreenCastStream {
+  pub node_id: u32,
+  pub width: u32,
+  pub height: u32,
+}
+
+// A ScreenCast portal session; the shared stream stays available until the session is closed
+// or the D-Bus connection is dropped
+pub struct ScreenCastSession {
+  connection: SyncConnection,
+  session_handle: String,
+}
+
+// Portal requests are identified by a token that has to be unique for the connection
+fn next_handle_token() -> String {
+  static REQUEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+  format!("kapt_{}", REQUEST_COUNT.fetch_add(1, Ordering::SeqCst))
+}
+
+// Calls a ScreenCast portal method and waits for the `Response` signal of the request object
+// it creates, passing the results of the request to `extract`
+fn call_portal_request<A: AppendAll, T: Send + 'static>(
+  connection: &SyncConnection,
+  method: &str,
+  handle_token: &str,
+  args: A,
+  extract: fn(&PropMap) -> Option<T>,
+) -> Result<T, String> {
+  // The request object path is known in advance, which allows subscribing to the response
+  // before the request is made
+  let sender = connection
+    .unique_name()
+    .trim_start_matches(':')
+    .replace('.', "_");
+  let request_path = format!(
+    "/org/freedesktop/portal/desktop/request/{}/{}",
+    sender, handle_token
+  );
+
+  let response: Arc<Mutex<Option<Result<T, String>>>> = Arc::new(Mutex::new(None));
+  let match_rule = MatchRule::new_signal("org.freedesktop.portal.Request", "Response")
+    .with_path(dbus::Path::new(request_path).map_err(|e| e.to_string())?);
+
+  let callback_response = response.clone();
+  let match_token = connection
+    .add_match(match_rule, move |(code, results): (u32, PropMap), _, _| {
+      // 0 means success, 1 means the user cancelled and 2 means any other error
+      let result = match code {
+        0 => extract(&results).ok_or_else(|| "The portal response is missing results.".to_string()),
+        1 => Err("The screen cast was cancelled.".to_string()),
+        _ => Err("The screen cast portal request failed.".to_string()),
+      };
+
+      *callback_response.lock().expect("Failed to lock response") = Some(result);
+      true
+    })
+    .map_err(|e| e.to_string())?;
+
+  let proxy = connection.with_proxy(PORTAL_DESTINATION, PORTAL_PATH, PORTAL_CALL_TIMEOUT);
+  let call_result: Result<(dbus::Path<'static>,), dbus::Error> =
+    proxy.method_call(SCREEN_CAST_INTERFACE, method, args);
+
+  let result = match call_result {
+    Ok(_) => {
+      let deadline = Instant::now() + PORTAL_RESPONSE_TIMEOUT;
+      loop {
+        if let Some(result) = response.lock().expect("Failed to lock response").take() {
+          break result;
+        }
+
+        if Instant::now() > deadline {
+          break Err(format!(
+            "Timed out waiting for the portal to answer {}.",
+            method
+          ));
+        }
+
+        if let Err(e) = connection.process(Duration::from_millis(100)) {
+          break Err(e.to_string());
+        }
+      }
+    }
+    Err(e) => Err(e.to_string()),
+  };
+
+  if let Err(e) = connection.remove_match(match_token) {
+    log::error!("{}", e);
+  }
+
+  result
+}
+
+fn string_option(value: &str) -> Variant<Box<dyn RefArg>> {
+  Variant(Box::new(value.to_string()))
+}
+
+fn parse_session_handle(results: &PropMap) -> Option<String> {
+  results
+    .get("session_handle")
+    .and_then(|session_handle| session_handle.0.as_str())
+    .map(|session_handle| session_handle.to_string())
+}
+
+// The `streams` result has the signature `a(ua{sv})`, where the `u` is the PipeWire node ID
+// and the dictionary holds properties such as the `size` of the stream
+fn parse_stream(results: &PropMap) -> Option<ScreenCastStream> {
+  let streams = results.get("streams")?;
+  let stream = streams.0.as_iter()?.next()?;
+  let mut stream_fields = stream.as_iter()?;
+
+  let node_id = stream_fields.next()?.as_u64()? as u32;
+  let mut width = 0;
+  let mut height = 0;
+
+  // Dictionaries are iterated as alternating keys and values
+  let mut properties = stream_fields.next()?.as_iter()?;
+  while let (Some(key), Some(value)) = (properties.next(), properties.next()) {
+    if key.as_str() == Some("size") {
+      let size = value.as_iter()?.next()?;
+      let mut size = size.as_iter()?;
+      width = size.next()?.as_i64()? as u32;
+      height = size.next()?.as_i64()? as u32;
+    }
+  }
+
+  Some(ScreenCastStream {
+    node_id,
+    width,
+    height,
+  })
+}
+
+impl ScreenCastSession {
+  // Asks the user to share a monitor (or a window if `select_window` is set) through
+  // xdg-desktop-portal
+  pub fn start(select_window: bool) -> Result<(Self, ScreenCastStream), String> {
+    let connection = SyncConnection::new_session().map_err(|e| e.to_string())?;
+
+    let handle_token = next_handle_token();
+    let mut options = PropMap::new();
+    options.insert("handle_token".to_string(), string_option(&handle_token));
+    options.insert(
+      "session_handle_token".to_string(),
+      string_option(&next_handle_token()),
+    );
+    let session_handle = call_portal_request(
+      &connection,
+      "CreateSession",
+      &handle_token,
+      (options,),
+      parse_session_handle,
+    )?;
+    let session_path = dbus::Path::new(session_handle.clone())?;
+
+    let session = Self {
+      connection,
+      session_handle,
+    };
+
+    // 1 is a monitor and 2 is a window
+    let source_type: u32 = if select_window { 2 } else { 1 };
+    let handle_token = next_handle_token();
+    let mut options = PropMap::new();
+    options.insert("handle_token".to_string(), string_option(&handle_token));
+    options.insert("types".to_string(), Variant(Box::new(source_type)));
+    options.insert("multiple".to_string(), Variant(Box::new(false)));
+    call_portal_request(
+      &session.connection,
+      "SelectSources",
+      &handle_token,
+      (session_path.clone(), options),
+      |_| Some(()),
+    )?;
+
+    let handle_token = next_handle_token();
+    let mut options = PropMap::new();
+    options.insert("handle_token".to_string(), string_option(&handle_token));
+    let stream = call_portal_request(
+      &session.connection,
+      "Start",
+      &handle_token,
+      (session_path, "", options),
+      parse_stream,
+    )?;
+
+    Ok((session, stream))
+  }
+
+  // Opens a connection to PipeWire that can access the session's stream. The default remote
+  // doesn't necessarily grant access to it (e.g. in a sandbox).
+  pub fn open_pipewire_remote(&self) -> Result<File, String> {
+    let session_path = dbus::Path::new(self.session_handle.as_str())?;
+    let proxy = self
+      .connection
+      .with_proxy(PORTAL_DESTINATION, PORTAL_PATH, PORTAL_CALL_TIMEOUT);
+
+    let (pipewire_remote,): (File,) = proxy
+      .method_call(
+        SCREEN_CAST_INTERFACE,
+        "OpenPipeWireRemote",
+        (session_path, PropMap::new()),
+      )
+      .map_err(|e| e.to_string())?;
+
+    Ok(pipewire_remote)
+  }
+
+  pub fn close(&self) {
+    let proxy = self.connection.with_proxy(
+      PORTAL_DESTINATION,
+      self.session_handle.as_str(),
+      PORTAL_CALL_TIMEOUT,
+    );
+
+    let result: Result<(), dbus::Error> =
+      proxy.method_call("org.freedesktop.portal.Session", "Close", ());
+    if let Err(e) = result {
+      log::error!("Failed to close the screen cast session: {}", e);
+    }
+  }
+}
+
+// Keeps the portal session and the GStreamer pipeline of a PipeWire screen cast alive
+struct PipeWireSource {
+  session: Option<ScreenCastSession>,
+  gstreamer_child: Option<Child>,
+}
+
+impl StreamSource for PipeWireSource {
+  fn stop(&mut self) {
+    // Ffmpeg finishes its last segment once the FIFO is closed
+    if let Some(mut gstreamer_child) = self.gstreamer_child.take() {
+      if let Err(e) = gstreamer_child.start_kill() {
+        log::error!("Failed to stop the GStreamer pipeline: {}", e);
+      }
+    }
+
+    // Closing the session waits for the portal, which shouldn't hold up the runtime
+    if let Some(session) = self.session.take() {
+      tokio::task::spawn_blocking(move || session.close());
+    }
+  }
+}
+
+// Records a PipeWire screen cast shared through xdg-desktop-portal, for Wayland sessions
+// where x11grab only sees a black XWayland root window. The screen cast is read by GStreamer
+// and passed to ffmpeg through a FIFO.
+pub struct PipeWireBackend;
+
+impl CaptureBackend for PipeWireBackend {
+  fn resolve_capture_geometry(&self, _capture_mode: &CaptureMode) -> Option<CaptureGeometry> {
+    // The user picks what to share in the portal's dialog, so the size is only known once
+    // the stream has started
+    Some(CaptureGeometry {
+      display: env::var("WAYLAND_DISPLAY").unwrap_or_default(),
+      width: 0,
+      height: 0,
+      x: 0,
+      y: 0,
+    })
+  }
+
+  fn start_video(
+    &self,
+    segment_dir: &str,
+    capture_mode: &CaptureMode,
+    capture_geometry: &CaptureGeometry,
+    encoding_profile: &EncodingProfile,
+  ) -> Result<FfmpegSegmentedStream, KaptError> {
+    let select_window = matches!(capture_mode, CaptureMode::Window { .. });
+    let (session, stream) =
+      ScreenCastSession::start(select_window).map_err(KaptError::ScreenCast)?;
+    let pipewire_remote = session.open_pipewire_remote().map_err(|e| {
+      session.close();
+      KaptError::ScreenCast(e)
+    })?;
+
+    let fifo_path = Path::new(segment_dir)
+      .join(format!("pipewire-{}.y4m", nanoid!(8)))
+      .to_string_lossy()
+      .to_string();
+    let mkfifo_status = std::process::Command::new("mkfifo")
+      .arg(&fifo_path)
+      .status()
+      .map_err(|e| KaptError::process_spawn("mkfifo", e))?;
+    if !mkfifo_status.success() {
+      session.close();
+      return Err(KaptError::ProcessFailed {
+        program: "mkfifo".to_string(),
+        message: format!("Failed to create {}", fifo_path),
+      });
+    }
+
+    // The PipeWire remote is passed as GStreamer's stdin, since the file descriptors the
+    // portal sends are closed on exec
+    let gstreamer_child = Command::new("gst-launch-1.0")
+      .args([
+        "-q",
+        "pipewiresrc",
+        "fd=0",
+        &format!("path={}", stream.node_id),
+        "do-timestamp=true",
+        "!",
+        "videorate",
+        "!",
+        "videoconvert",
+        "!",
+        &format!(
+          "video/x-raw,format=I420,framerate={}/1",
+          encoding_profile.framerate
+        ),
+        "!",
+        "y4menc",
+        "!",
+        "filesink",
+        &format!("location={}", fifo_path),
+      ])
+      .stdin(Stdio::from(pipewire_remote))
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .kill_on_drop(true)
+      .spawn()
+      .map_err(|e| {
+        session.close();
+        KaptError::process_spawn("gst-launch-1.0", e)
+      })?;
+
+    let capture_geometry = CaptureGeometry {
+      width: stream.width,
+      height: stream.height,
+      ..capture_geometry.clone()
+    };
+
+    // Y4M has no timestamps, so the frames are stamped with the wall clock like x11grab's
+    let input_args = vec![
+      "-use_wallclock_as_timestamps".into(),
+      "1".into(),
+      "-f".into(),
+      "yuv4mpegpipe".into(),
+      "-i".into(),
+      fifo_path,
+    ];
+
+    let mut source = PipeWireSource {
+      session: Some(session),
+      gstreamer_child: Some(gstreamer_child),
+    };
+
+    match start_segmented_video(segment_dir, input_args, &capture_geometry, encoding_profile) {
+      Ok(mut video) => {
+        video.source = Some(Box::new(source));
+        Ok(video)
+      }
+      Err(e) => {
+        source.stop();
+        Err(e)
+      }
+    }
+  }
+}
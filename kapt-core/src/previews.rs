@@ -0,0 +1,133 @@
+// Images that show what's in a Kapture without playing it: a poster frame and a sprite sheet
+// of frames spread over the Kapture for scrubbing previews. They're saved next to the video so
+// that they stay with it.
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::error::KaptError;
+use crate::ffmpeg_time::FfmpegDuration;
+
+const POSTER_WIDTH: u32 = 640;
+const SPRITE_TILE_WIDTH: u32 = 160;
+// The sprite sheet is a single row of this many frames
+const SPRITE_TILES: u32 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KapturePreviews {
+  pub poster_path: String,
+  pub sprite_path: String,
+  // The number of frames in the sprite sheet, from the start of the Kapture to its end
+  pub sprite_tiles: u32,
+}
+
+// `<video>-<suffix>.jpg` next to the video
+fn get_preview_path(video_path: &str, suffix: &str) -> String {
+  let video_path = Path::new(video_path);
+  let file_stem = video_path
+    .file_stem()
+    .map(|file_stem| file_stem.to_string_lossy().to_string())
+    .unwrap_or_default();
+
+  video_path
+    .with_file_name(format!("{}-{}.jpg", file_stem, suffix))
+    .to_string_lossy()
+    .to_string()
+}
+
+async fn run_ffmpeg(mut command: Command) -> Result<(), KaptError> {
+  let output = command
+    .stdin(Stdio::null())
+    .kill_on_drop(true)
+    .output()
+    .await
+    .map_err(|e| KaptError::process_spawn("ffmpeg", e))?;
+
+  if output.status.success() {
+    Ok(())
+  } else {
+    // The last line of the logs has the reason
+    let logs = String::from_utf8_lossy(&output.stderr);
+    Err(KaptError::ProcessFailed {
+      program: "ffmpeg".to_string(),
+      message: logs.lines().last().unwrap_or_default().to_string(),
+    })
+  }
+}
+
+// Makes the previews of the video at `video_path`, which is `duration` milliseconds long
+pub async fn create_previews(
+  video_path: &str,
+  duration: u128,
+) -> Result<KapturePreviews, KaptError> {
+  let poster_path = get_preview_path(video_path, "poster");
+  let sprite_path = get_preview_path(video_path, "sprite");
+
+  // The middle of the Kapture is more likely to show what it's about than its first frame
+  let mut command = Command::new("ffmpeg");
+  command
+    .args(["-hide_banner", "-loglevel", "error"])
+    .args([
+      "-ss",
+      &FfmpegDuration::from_millis(duration / 2).to_string(),
+    ])
+    .args(["-i", video_path])
+    .args(["-frames:v", "1"])
+    .args(["-vf", &format!("scale={}:-2", POSTER_WIDTH)])
+    .args(["-y", &poster_path]);
+  run_ffmpeg(command).await?;
+
+  // Picks `SPRITE_TILES` frames evenly spread over the Kapture and puts them in a row
+  let mut command = Command::new("ffmpeg");
+  command
+    .args(["-hide_banner", "-loglevel", "error"])
+    .args(["-i", video_path])
+    .args(["-frames:v", "1"])
+    .args([
+      "-vf",
+      &format!(
+        "fps={}/{},scale={}:-2,tile={}x1",
+        SPRITE_TILES as u128 * 1000,
+        duration.max(1),
+        SPRITE_TILE_WIDTH,
+        SPRITE_TILES
+      ),
+    ])
+    .args(["-y", &sprite_path]);
+  if let Err(e) = run_ffmpeg(command).await {
+    remove_previews_at(&poster_path, &sprite_path);
+    return Err(e);
+  }
+
+  Ok(KapturePreviews {
+    poster_path,
+    sprite_path,
+    sprite_tiles: SPRITE_TILES,
+  })
+}
+
+fn remove_previews_at(poster_path: &str, sprite_path: &str) {
+  for preview_path in &[poster_path, sprite_path] {
+    if Path::new(preview_path).exists() {
+      if let Err(e) = fs::remove_file(preview_path) {
+        log::error!("Failed to remove {}: {}", preview_path, e);
+      }
+    }
+  }
+}
+
+// Removes whatever previews of the video at `video_path` have been written, e.g. when making
+// them was cancelled
+pub fn remove_previews_of(video_path: &str) {
+  remove_previews_at(
+    &get_preview_path(video_path, "poster"),
+    &get_preview_path(video_path, "sprite"),
+  );
+}
+
+// Removes the previews' files, e.g. when their Kapture is deleted
+pub fn remove_previews(previews: &KapturePreviews) {
+  remove_previews_at(&previews.poster_path, &previews.sprite_path);
+}
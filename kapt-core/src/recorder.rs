@@ -0,0 +1,255 @@
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::config::RecorderConfig;
+use crate::encoding::KaptureFormat;
+use crate::error::KaptError;
+use crate::events::KaptEvent;
+use crate::kapture::KaptureJob;
+use crate::library::{KaptureEntry, KaptureLibrary};
+use crate::markers::{self, Marker, MarkerKind};
+use crate::recording::BufferStatus;
+use crate::state::KaptState;
+use crate::utils::get_current_time;
+use crate::{kapture, recording};
+
+// Keeps a rolling buffer of the screen and audio while it's active, from which Kaptures of
+// the last few seconds can be made at any time. Clones share the same recording.
+//
+// Recording needs a Tokio runtime with the IO and time drivers enabled, so `activate` and
+// `kapture` have to be awaited on one.
+#[derive(Clone)]
+pub struct Recorder {
+  state: Arc<RwLock<KaptState>>,
+  events: broadcast::Sender<KaptEvent>,
+  // The Kaptures that are currently being made
+  kaptures: Arc<Mutex<Vec<Arc<KaptureJob>>>>,
+  // Where finished Kaptures are recorded, if anywhere
+  library: Option<Arc<KaptureLibrary>>,
+}
+
+impl Recorder {
+  pub fn new(config: RecorderConfig) -> Self {
+    Self {
+      state: Arc::new(RwLock::new(KaptState::new(config))),
+      events: broadcast::channel(16).0,
+      kaptures: Arc::new(Mutex::new(vec![])),
+      library: None,
+    }
+  }
+
+  // Adds every Kapture made from now on to `library`
+  pub fn with_library(mut self, library: Arc<KaptureLibrary>) -> Self {
+    self.library = Some(library);
+    self
+  }
+
+  pub(crate) fn state(&self) -> &RwLock<KaptState> {
+    &self.state
+  }
+
+  pub fn config(&self) -> Result<RecorderConfig, KaptError> {
+    Ok(self.state.read()?.config.clone())
+  }
+
+  // Replaces the whole configuration. Changes to how the screen is recorded take effect the
+  // next time the recorder is activated.
+  pub fn configure(&self, config: RecorderConfig) -> Result<(), KaptError> {
+    config.validate()?;
+    self.state.write()?.config = config;
+    Ok(())
+  }
+
+  // Changes part of the configuration and returns the result, e.g.
+  // `recorder.update_config(|config| config.audio_source = 1)`
+  pub fn update_config(
+    &self,
+    update: impl FnOnce(&mut RecorderConfig),
+  ) -> Result<RecorderConfig, KaptError> {
+    let mut state = self.state.write()?;
+
+    let mut config = state.config.clone();
+    update(&mut config);
+    config.validate()?;
+
+    state.config = config.clone();
+    Ok(config)
+  }
+
+  pub fn is_active(&self) -> Result<bool, KaptError> {
+    Ok(self.state.read()?.is_active())
+  }
+
+  // Starts recording into the buffer
+  pub async fn activate(&self) -> Result<(), KaptError> {
+    recording::activate_kapt(self).await?;
+    self.publish_event(KaptEvent::ActivationToggled(true));
+    Ok(())
+  }
+
+  // Stops recording and clears the buffer
+  pub async fn deactivate(&self) -> Result<(), KaptError> {
+    recording::deactivate_kapt(self.state()).await?;
+    self.publish_event(KaptEvent::ActivationToggled(false));
+    self.publish_event(KaptEvent::MarkersChanged(vec![]));
+    Ok(())
+  }
+
+  // Saves the last `duration` of the buffer in the configured format and returns where the
+  // video and its previews were saved
+  pub async fn kapture(&self, duration: Duration) -> Result<KaptureEntry, KaptError> {
+    let format = self.config()?.kapture_format;
+    self.kapture_at(get_current_time(), duration, format).await
+  }
+
+  // Saves the `duration` before `end_time` (a unix timestamp in milliseconds), e.g. the time
+  // at which the user asked for the Kapture. If `end_time` is in the future, the Kapture is
+  // made once it has been recorded; the buffer has to hold the whole Kapture by then. Its
+  // progress is published as `KaptureProgress` events.
+  pub async fn kapture_at(
+    &self,
+    end_time: u128,
+    duration: Duration,
+    format: KaptureFormat,
+  ) -> Result<KaptureEntry, KaptError> {
+    // The buffer can never hold a longer Kapture, so it would be waited for in vain
+    let max_seconds_cached = self.config()?.max_seconds_cached;
+    if duration > Duration::from_secs(max_seconds_cached as u64) {
+      return Err(KaptError::InvalidSettings(format!(
+        "A Kapture can be at most {} seconds long, since that's how much the buffer holds.",
+        max_seconds_cached
+      )));
+    }
+
+    let job = Arc::new(KaptureJob::new());
+    self.kaptures.lock()?.push(job.clone());
+
+    let kapture = kapture::create_kapture(self, &job, end_time, duration.as_millis(), format).await;
+
+    self
+      .kaptures
+      .lock()?
+      .retain(|kapture| !Arc::ptr_eq(kapture, &job));
+
+    let kapture = kapture?;
+
+    // The video has been saved either way, so a broken index only gets reported
+    if let Some(library) = self.library.as_ref() {
+      if let Err(e) = library.add(&kapture) {
+        self.report_error(e);
+      }
+    }
+
+    self.publish_event(KaptEvent::KaptureCreated(Box::new(kapture.clone())));
+    Ok(kapture)
+  }
+
+  // Cancels the Kapture with the ID from its `KaptureProgress` events, or every Kapture that's
+  // being made if `kapture_id` is `None`
+  pub fn cancel_kapture(&self, kapture_id: Option<&str>) -> Result<(), KaptError> {
+    for kapture in self.kaptures.lock()?.iter() {
+      if kapture_id.is_none() || kapture_id == Some(kapture.id.as_str()) {
+        kapture.cancel();
+      }
+    }
+
+    Ok(())
+  }
+
+  // Marks the moment at `time` (a unix timestamp in milliseconds) in the buffer
+  pub fn add_marker(
+    &self,
+    time: u128,
+    kind: MarkerKind,
+    label: Option<String>,
+  ) -> Result<Marker, KaptError> {
+    let marker = Marker::new(time, kind, label);
+
+    let markers = {
+      let mut state = self.state.write()?;
+
+      if !state.is_active() {
+        return Err(KaptError::NotActive);
+      }
+
+      markers::insert_marker(&mut state.markers, marker.clone());
+      state.markers.clone()
+    };
+
+    self.publish_event(KaptEvent::MarkersChanged(markers));
+    Ok(marker)
+  }
+
+  pub fn remove_marker(&self, marker_id: &str) -> Result<(), KaptError> {
+    let markers = {
+      let mut state = self.state.write()?;
+      state.markers.retain(|marker| marker.id != marker_id);
+      state.markers.clone()
+    };
+
+    self.publish_event(KaptEvent::MarkersChanged(markers));
+    Ok(())
+  }
+
+  // The markers whose footage is still buffered, oldest first
+  pub fn markers(&self) -> Result<Vec<Marker>, KaptError> {
+    Ok(self.state.read()?.markers.clone())
+  }
+
+  // Saves the footage from `start_time` to `end_time` (unix timestamps in milliseconds), e.g.
+  // between two markers. The start has to still be in the buffer and the end can't be in the
+  // future.
+  pub async fn export_range(
+    &self,
+    start_time: u128,
+    end_time: u128,
+    format: KaptureFormat,
+  ) -> Result<KaptureEntry, KaptError> {
+    if end_time <= start_time {
+      return Err(KaptError::InvalidRange(
+        "The end of the range has to be after its start.".to_string(),
+      ));
+    }
+
+    if end_time > get_current_time() {
+      return Err(KaptError::InvalidRange(
+        "The end of the range hasn't been recorded yet.".to_string(),
+      ));
+    }
+
+    if let Some(oldest_segment) = self.state.read()?.video_segments.front() {
+      if start_time < oldest_segment.start_time {
+        return Err(KaptError::InvalidRange(
+          "The start of the range isn't buffered anymore.".to_string(),
+        ));
+      }
+    }
+
+    let duration = u64::try_from(end_time - start_time)
+      .map(Duration::from_millis)
+      .map_err(|_| KaptError::InvalidRange("The range is too long.".to_string()))?;
+    self.kapture_at(end_time, duration, format).await
+  }
+
+  pub fn buffer_status(&self) -> Result<BufferStatus, KaptError> {
+    Ok(recording::get_buffer_status(&*self.state.read()?))
+  }
+
+  pub fn subscribe_events(&self) -> broadcast::Receiver<KaptEvent> {
+    self.events.subscribe()
+  }
+
+  pub(crate) fn publish_event(&self, event: KaptEvent) {
+    // Sending only fails when nobody is listening
+    let _ = self.events.send(event);
+  }
+
+  // Errors of background tasks can't be returned to the caller, so they're published as
+  // events instead
+  pub fn report_error(&self, error: KaptError) {
+    log::error!("{}", error);
+    self.publish_event(KaptEvent::Error(error));
+  }
+}
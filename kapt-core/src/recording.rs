@@ -0,0 +1,430 @@
+use crate::backend::{CaptureBackend, SEGMENT_SECONDS};
+use crate::display;
+use crate::display::{CaptureGeometry, CaptureMode};
+use crate::encoding::EncodingProfile;
+use crate::error::KaptError;
+use crate::events::KaptEvent;
+use crate::markers::{self, Marker};
+use crate::recorder::Recorder;
+use crate::state::FfmpegActiveRecording;
+use crate::state::FfmpegSegment;
+use crate::state::FfmpegSegmentedStream;
+use crate::state::KaptState;
+use crate::state::SegmentDir;
+use crate::utils::create_temp_path;
+use crate::utils::get_current_time;
+use nanoid::nanoid;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+// Removes the segments that ended before `cache_start_time`
+fn evict_expired_segments(segments: &mut VecDeque<Arc<FfmpegSegment>>, cache_start_time: u128) {
+  while let Some(oldest_segment) = segments.front() {
+    if oldest_segment.end_time >= cache_start_time {
+      break;
+    }
+
+    segments.pop_front();
+  }
+}
+
+fn get_segments_size(segments: &VecDeque<Arc<FfmpegSegment>>) -> u64 {
+  segments.iter().map(|segment| segment.size).sum()
+}
+
+// Removes the oldest segments of either stream until the buffer fits into `max_bytes`
+fn evict_oversized_segments(state: &mut KaptState, max_bytes: u64) {
+  let mut cached_bytes =
+    get_segments_size(&state.video_segments) + get_segments_size(&state.audio_segments);
+
+  while cached_bytes > max_bytes {
+    let oldest_segments = match (state.video_segments.front(), state.audio_segments.front()) {
+      (Some(video_segment), Some(audio_segment)) => {
+        if video_segment.start_time <= audio_segment.start_time {
+          &mut state.video_segments
+        } else {
+          &mut state.audio_segments
+        }
+      }
+      (Some(_), None) => &mut state.video_segments,
+      (None, Some(_)) => &mut state.audio_segments,
+      (None, None) => break,
+    };
+
+    if let Some(oldest_segment) = oldest_segments.pop_front() {
+      cached_bytes -= oldest_segment.size;
+    }
+  }
+}
+
+// How much footage is currently cached
+#[derive(Serialize, Debug)]
+pub struct BufferStatus {
+  // From the start of the oldest video segment to the end of the newest one
+  pub cached_milliseconds: u64,
+  pub cached_bytes: u64,
+  pub max_seconds_cached: u32,
+  pub max_cache_megabytes: Option<u32>,
+}
+
+pub fn get_buffer_status(state: &KaptState) -> BufferStatus {
+  let cached_milliseconds = match (state.video_segments.front(), state.video_segments.back()) {
+    (Some(oldest_segment), Some(newest_segment)) => newest_segment
+      .end_time
+      .saturating_sub(oldest_segment.start_time)
+      as u64,
+    _ => 0,
+  };
+
+  BufferStatus {
+    cached_milliseconds,
+    cached_bytes: get_segments_size(&state.video_segments)
+      + get_segments_size(&state.audio_segments),
+    max_seconds_cached: state.config.max_seconds_cached,
+    max_cache_megabytes: state.config.max_cache_megabytes,
+  }
+}
+
+// Moves the segments ffmpeg has finished into the buffer and evicts the ones that are older
+// than the maximum cached duration or don't fit into the maximum cache size
+pub fn collect_segments(state_lock: &RwLock<KaptState>) -> Result<(), KaptError> {
+  let mut state = state_lock.write()?;
+  let state = &mut *state;
+
+  if let (Some(active_recording), Some(segment_dir)) =
+    (state.active_recording.as_mut(), state.segment_dir.as_ref())
+  {
+    let video_segments = active_recording.video.read_new_segments(segment_dir)?;
+    let audio_segments = active_recording.audio.read_new_segments(segment_dir)?;
+    state.video_segments.extend(video_segments);
+    state.audio_segments.extend(audio_segments);
+  }
+
+  let cache_start_time =
+    get_current_time().saturating_sub(state.config.max_seconds_cached as u128 * 1000);
+  evict_expired_segments(&mut state.video_segments, cache_start_time);
+  evict_expired_segments(&mut state.audio_segments, cache_start_time);
+
+  if let Some(max_cache_megabytes) = state.config.max_cache_megabytes {
+    evict_oversized_segments(state, max_cache_megabytes as u64 * 1024 * 1024);
+  }
+
+  Ok(())
+}
+
+// Removes the markers that are older than the buffer. Returns the remaining markers if any
+// were removed.
+fn evict_expired_markers(state_lock: &RwLock<KaptState>) -> Result<Option<Vec<Marker>>, KaptError> {
+  let mut state = state_lock.write()?;
+  let state = &mut *state;
+
+  let cache_start_time = match state.video_segments.front() {
+    Some(oldest_segment) => oldest_segment.start_time,
+    None => return Ok(None),
+  };
+
+  if markers::evict_expired_markers(&mut state.markers, cache_start_time) {
+    Ok(Some(state.markers.clone()))
+  } else {
+    Ok(None)
+  }
+}
+
+// Returns why the streams of the recording stopped, if ffmpeg exited on its own (e.g. because
+// the input device disappeared)
+fn take_stream_failures(state_lock: &RwLock<KaptState>) -> Result<Vec<KaptError>, KaptError> {
+  let state = state_lock.read()?;
+
+  Ok(
+    state
+      .active_recording
+      .iter()
+      .flat_map(|active_recording| vec![&active_recording.video, &active_recording.audio])
+      .filter_map(|stream| stream.take_failure())
+      .collect(),
+  )
+}
+
+// Runs `f` on the blocking thread pool, for work that waits on other processes (e.g. `xrandr`
+// or the screen cast portal) and would otherwise hold up the runtime
+async fn run_blocking<T: Send + 'static>(
+  description: &str,
+  f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, KaptError> {
+  tokio::task::spawn_blocking(f)
+    .await
+    // `f` panicked
+    .map_err(|e| KaptError::process_spawn(description, e))
+}
+
+// Starts the video stream, which can take a while (e.g. until the user has answered the screen
+// cast portal's dialog)
+async fn start_video(
+  capture_backend: &'static dyn CaptureBackend,
+  segment_dir: &SegmentDir,
+  capture_mode: &CaptureMode,
+  capture_geometry: &CaptureGeometry,
+  encoding_profile: &EncodingProfile,
+) -> Result<FfmpegSegmentedStream, KaptError> {
+  let segment_dir = segment_dir.path.clone();
+  let capture_mode = capture_mode.clone();
+  let capture_geometry = capture_geometry.clone();
+  let encoding_profile = encoding_profile.clone();
+
+  run_blocking("the video stream", move || {
+    capture_backend.start_video(
+      &segment_dir,
+      &capture_mode,
+      &capture_geometry,
+      &encoding_profile,
+    )
+  })
+  .await?
+}
+
+// Restarts the video stream with the window's new geometry if it was moved or resized
+async fn follow_capture_window(state_lock: &RwLock<KaptState>) -> Result<(), KaptError> {
+  let (capture_backend, capture_mode, capture_geometry, segment_dir, encoding_profile) = {
+    let state = state_lock.read()?;
+    (
+      state.config.capture_backend.backend(),
+      state.config.capture_mode.clone(),
+      state.active_capture_geometry.clone(),
+      state.segment_dir.clone(),
+      state.config.encoding_profile.clone(),
+    )
+  };
+
+  let window_id = match &capture_mode {
+    CaptureMode::Window { id } if capture_backend.can_follow_windows() => *id,
+    _ => return Ok(()),
+  };
+
+  // Keep the previous geometry if the window can't be found (e.g. it's minimized)
+  let window_geometry =
+    match run_blocking("xwininfo", move || display::get_window_geometry(window_id)).await? {
+      Some(window_geometry) => window_geometry,
+      None => return Ok(()),
+    };
+
+  let segment_dir = match segment_dir {
+    Some(segment_dir) => segment_dir,
+    None => return Ok(()),
+  };
+
+  if capture_geometry.as_ref() == Some(&window_geometry) {
+    return Ok(());
+  }
+
+  // Start the new stream before stopping the old one so that no footage is lost
+  let mut video = start_video(
+    capture_backend,
+    &segment_dir,
+    &capture_mode,
+    &window_geometry,
+    &encoding_profile,
+  )
+  .await?;
+
+  {
+    let mut state = state_lock.write()?;
+
+    if let Some(active_recording) = state.active_recording.as_mut() {
+      std::mem::swap(&mut active_recording.video, &mut video);
+      state.active_capture_geometry = Some(window_geometry);
+    }
+  }
+
+  // `video` is now the old stream (or the new one if Kapt was deactivated in the meantime)
+  video.stop().await;
+  let video_segments = video.read_new_segments(&segment_dir)?;
+
+  let mut state = state_lock.write()?;
+  state.video_segments.extend(video_segments);
+
+  Ok(())
+}
+
+pub async fn activate_kapt(recorder: &Recorder) -> Result<(), KaptError> {
+  let state_lock = recorder.state();
+
+  {
+    let state = state_lock.read()?;
+
+    if state.is_active() {
+      log::info!("Kapt has already been activated.");
+      return Ok(());
+    }
+  }
+
+  // Resolve the part of the screen to record for this session
+  let (capture_backend, capture_mode, audio_source, encoding_profile) = {
+    let state = state_lock.read()?;
+    (
+      state.config.capture_backend.backend(),
+      state.config.capture_mode.clone(),
+      state.config.audio_source,
+      state.config.encoding_profile.clone(),
+    )
+  };
+
+  let capture_geometry = {
+    let capture_mode = capture_mode.clone();
+    run_blocking("the capture geometry detection", move || {
+      capture_backend.resolve_capture_geometry(&capture_mode)
+    })
+    .await?
+    .ok_or(KaptError::CaptureGeometryUnavailable)?
+  };
+
+  log::info!("Starting the recording...");
+  let recording_session_id = nanoid!();
+
+  // Removed again if the streams fail to start
+  let segment_dir = Arc::new(SegmentDir::create(create_temp_path(&format!(
+    "kapt-{}",
+    recording_session_id
+  )))?);
+
+  let mut video = start_video(
+    capture_backend,
+    &segment_dir,
+    &capture_mode,
+    &capture_geometry,
+    &encoding_profile,
+  )
+  .await?;
+  let audio = match capture_backend.start_audio(&segment_dir.path, audio_source) {
+    Ok(audio) => audio,
+    Err(e) => {
+      video.stop().await;
+      return Err(e);
+    }
+  };
+
+  log::debug!("Ffmpeg process spawned...");
+
+  // Generating a recording session ID, unless another activation has finished while the
+  // streams were starting, in which case that one's recording is kept
+  let superseded_streams = {
+    let mut state = state_lock.write()?;
+
+    if state.is_active() {
+      Some((video, audio))
+    } else {
+      state.recording_session_id = Some(recording_session_id.clone());
+      state.segment_dir = Some(segment_dir);
+      state.video_segments = VecDeque::new();
+      state.audio_segments = VecDeque::new();
+      // The backend might only know the actual size once the stream has started
+      state.active_capture_geometry = video.capture_geometry.clone();
+      state.active_recording = Some(FfmpegActiveRecording { video, audio });
+      None
+    }
+  };
+
+  if let Some((mut video, mut audio)) = superseded_streams {
+    log::info!("Kapt has already been activated.");
+    video.stop().await;
+    audio.stop().await;
+    return Ok(());
+  }
+
+  use tokio::time::{sleep, Duration};
+
+  let recorder = recorder.clone();
+  tokio::spawn(async move {
+    let state_lock = recorder.state();
+
+    loop {
+      // Pick up the segments ffmpeg finished in the meantime
+      sleep(Duration::from_secs(SEGMENT_SECONDS as u64)).await;
+
+      // Check if the session ID is most recent
+      let current_recording_session_id = match state_lock.read() {
+        Ok(state) => state.recording_session_id.clone(),
+        Err(e) => {
+          recorder.report_error(e.into());
+          break;
+        }
+      };
+
+      if current_recording_session_id != Some(recording_session_id.clone()) {
+        break;
+      }
+
+      // The recording keeps going if a single round fails
+      if let Err(e) = follow_capture_window(state_lock).await {
+        recorder.report_error(e);
+      }
+
+      if let Err(e) = collect_segments(state_lock) {
+        recorder.report_error(e);
+      }
+
+      match evict_expired_markers(state_lock) {
+        Ok(Some(markers)) => recorder.publish_event(KaptEvent::MarkersChanged(markers)),
+        Ok(None) => {}
+        Err(e) => recorder.report_error(e),
+      }
+
+      match take_stream_failures(state_lock) {
+        Ok(failures) => failures.into_iter().for_each(|e| recorder.report_error(e)),
+        Err(e) => recorder.report_error(e),
+      }
+    }
+  });
+
+  Ok(())
+}
+
+pub async fn stop_recordings(state_lock: &RwLock<KaptState>) -> Result<(), KaptError> {
+  let active_recording = {
+    let mut state = state_lock.write()?;
+
+    if !state.is_active() {
+      log::info!("Kapt isn't currently active.");
+      return Ok(());
+    }
+
+    state.recording_session_id = None;
+    Option::take(&mut state.active_recording)
+  };
+
+  log::info!("Stopping the recording...");
+
+  if let Some(mut active_recording) = active_recording {
+    active_recording.video.stop().await;
+    active_recording.audio.stop().await;
+
+    let mut state = state_lock.write()?;
+    let state = &mut *state;
+
+    if let Some(segment_dir) = state.segment_dir.as_ref() {
+      let video_segments = active_recording.video.read_new_segments(segment_dir)?;
+      let audio_segments = active_recording.audio.read_new_segments(segment_dir)?;
+      state.video_segments.extend(video_segments);
+      state.audio_segments.extend(audio_segments);
+    }
+  }
+
+  Ok(())
+}
+
+// The segment directory is removed once the Kaptures that are still being assembled from it
+// are done with its segments
+pub async fn deactivate_kapt(state_lock: &RwLock<KaptState>) -> Result<(), KaptError> {
+  stop_recordings(state_lock).await?;
+
+  let mut state = state_lock.write()?;
+  state.video_segments = VecDeque::new();
+  state.audio_segments = VecDeque::new();
+  state.recording_session_id = None;
+  state.active_recording = None;
+  state.active_capture_geometry = None;
+  state.markers = vec![];
+  state.segment_dir = None;
+
+  Ok(())
+}
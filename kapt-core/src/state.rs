@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::oneshot;
+
+use crate::config::RecorderConfig;
+use crate::display::CaptureGeometry;
+use crate::error::KaptError;
+use crate::ffmpeg_time::FfmpegDuration;
+use crate::markers::Marker;
+
+pub struct KaptState {
+  // How the recorder has been configured
+  pub config: RecorderConfig,
+
+  // The ffmpeg processes of the current recording session
+  pub active_recording: Option<FfmpegActiveRecording>,
+
+  // Used to prevent a thread from continuing a chunk recording when the
+  // associated recording session has ended
+  pub recording_session_id: Option<String>,
+
+  // The temporary directory holding the segments and segment lists of the recording session
+  pub segment_dir: Option<Arc<SegmentDir>>,
+
+  // The finished segments of the current recording session, oldest first
+  pub video_segments: VecDeque<Arc<FfmpegSegment>>,
+  pub audio_segments: VecDeque<Arc<FfmpegSegment>>,
+
+  // The capture area used by the current recording session
+  pub active_capture_geometry: Option<CaptureGeometry>,
+
+  // The moments marked in the buffer, oldest first
+  pub markers: Vec<Marker>,
+}
+
+impl KaptState {
+  pub fn is_active(&self) -> bool {
+    self.active_recording.is_some()
+  }
+
+  pub fn new(config: RecorderConfig) -> Self {
+    Self {
+      config,
+      active_recording: None,
+      recording_session_id: None,
+      segment_dir: None,
+      video_segments: VecDeque::new(),
+      audio_segments: VecDeque::new(),
+      active_capture_geometry: None,
+      markers: vec![],
+    }
+  }
+}
+
+// A recording that's currently in process
+pub struct FfmpegActiveRecording {
+  pub video: FfmpegSegmentedStream,
+  pub audio: FfmpegSegmentedStream,
+}
+
+// A long-lived ffmpeg process which splits its output into segments using the segment muxer
+pub struct FfmpegSegmentedStream {
+  // Ffmpeg is stopped by writing 'q' to its stdin
+  stdin: Option<ChildStdin>,
+  // The CSV list ffmpeg appends to every time a segment is finished
+  pub segment_list_path: String,
+  // The number of entries of the segment list that have already been read
+  pub segments_read: usize,
+  // The unix timestamp (in milliseconds) of the first frame, parsed from ffmpeg's logs
+  pub start_time: Arc<Mutex<Option<u128>>>,
+  // Resolves once the ffmpeg process has exited
+  pub exit_rx: oneshot::Receiver<()>,
+  // Set if ffmpeg exited unsuccessfully
+  failure: Arc<Mutex<Option<KaptError>>>,
+  // The area of the screen being recorded (only for video streams)
+  pub capture_geometry: Option<CaptureGeometry>,
+  // Whatever feeds the ffmpeg input when it isn't read by ffmpeg directly (e.g. a GStreamer
+  // pipeline writing to a FIFO)
+  pub source: Option<Box<dyn StreamSource>>,
+}
+
+// Something feeding the input of a segmented ffmpeg stream
+pub trait StreamSource: Send + Sync {
+  // Stops feeding the input, which makes ffmpeg finish its last segment
+  fn stop(&mut self);
+}
+
+use lazy_static::lazy_static;
+
+// Returns the unix timestamp (in milliseconds) of the stream's first frame if `line` is the
+// part of ffmpeg's input description that has it (e.g. `Duration: N/A, start: 1625151128.627318`)
+fn parse_start_time(line: &str) -> Option<u128> {
+  use regex::Regex;
+  lazy_static! {
+    static ref START_TIME_RE: Regex =
+      Regex::new(r#"start: (\d+\.\d+)"#).expect("Failed to compile regex");
+  };
+
+  let cap = START_TIME_RE.captures(line)?;
+  let start_time = cap.get(1)?.as_str().parse::<FfmpegDuration>().ok()?;
+
+  Some(start_time.as_millis())
+}
+
+impl FfmpegSegmentedStream {
+  // Spawns the ffmpeg `command` whose segment muxer writes to `segment_list_path`
+  pub fn spawn(
+    mut command: Command,
+    segment_list_path: String,
+    capture_geometry: Option<CaptureGeometry>,
+  ) -> Result<Self, KaptError> {
+    let mut child = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true)
+      .spawn()
+      .map_err(|e| KaptError::process_spawn("ffmpeg", e))?;
+
+    let stdin = child.stdin.take();
+    let stderr = child
+      .stderr
+      .take()
+      .ok_or_else(|| KaptError::process_spawn("ffmpeg", "Failed to read its logs."))?;
+
+    let start_time = Arc::new(Mutex::new(None));
+    let failure = Arc::new(Mutex::new(None));
+    let (exit_tx, exit_rx) = oneshot::channel();
+
+    // Ffmpeg logs the start time of its input to stderr right after it starts, so the
+    // logs have to be read while the process is still running
+    let task_start_time = start_time.clone();
+    let task_failure = failure.clone();
+    tokio::spawn(async move {
+      let mut last_line = String::new();
+
+      let mut lines = BufReader::new(stderr).lines();
+      while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(unix_timestamp) = parse_start_time(&line) {
+          if let Ok(mut start_time) = task_start_time.lock() {
+            start_time.get_or_insert(unix_timestamp);
+          }
+        }
+
+        last_line = line;
+      }
+
+      // Ffmpeg exits with 0 when it's stopped with 'q', so anything else means that the
+      // recording broke off (e.g. the input device disappeared)
+      let message = match child.wait().await {
+        Ok(status) if status.success() => None,
+        Ok(_) => Some(last_line),
+        Err(e) => Some(e.to_string()),
+      };
+
+      if let (Some(message), Ok(mut failure)) = (message, task_failure.lock()) {
+        *failure = Some(KaptError::ProcessFailed {
+          program: "ffmpeg".to_string(),
+          message,
+        });
+      }
+
+      let _ = exit_tx.send(());
+    });
+
+    Ok(Self {
+      stdin,
+      segment_list_path,
+      segments_read: 0,
+      start_time,
+      exit_rx,
+      failure,
+      capture_geometry,
+      source: None,
+    })
+  }
+
+  // Returns the error ffmpeg exited with, once
+  pub fn take_failure(&self) -> Option<KaptError> {
+    self.failure.lock().ok()?.take()
+  }
+
+  // Reads the segments that ffmpeg finished since the last call
+  pub fn read_new_segments(
+    &mut self,
+    segment_dir: &Arc<SegmentDir>,
+  ) -> Result<Vec<Arc<FfmpegSegment>>, KaptError> {
+    let start_time = match *self.start_time.lock()? {
+      Some(start_time) => start_time,
+      // No segments can have been written before the input started
+      None => return Ok(vec![]),
+    };
+
+    // The list only exists once the first segment is finished
+    let segment_list = match fs::read_to_string(&self.segment_list_path) {
+      Ok(segment_list) => segment_list,
+      Err(_) => return Ok(vec![]),
+    };
+
+    // Ffmpeg might be in the middle of writing the last line
+    let complete_lines_end = segment_list.rfind('\n').map_or(0, |i| i + 1);
+
+    let mut segments = vec![];
+    // Each line has the format `segment_filename,segment_start_time,segment_end_time`, with
+    // the times in seconds relative to the start of the stream
+    for line in segment_list[..complete_lines_end]
+      .lines()
+      .skip(self.segments_read)
+    {
+      let mut fields = line.rsplitn(3, ',');
+      let end_seconds = fields.next().and_then(|s| s.parse::<f64>().ok());
+      let start_seconds = fields.next().and_then(|s| s.parse::<f64>().ok());
+      let file_name = fields.next();
+
+      self.segments_read += 1;
+
+      if let (Some(file_name), Some(start_seconds), Some(end_seconds)) =
+        (file_name, start_seconds, end_seconds)
+      {
+        let path = Path::new(&segment_dir.path)
+          .join(file_name.trim_matches('"'))
+          .to_string_lossy()
+          .to_string();
+        let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+
+        segments.push(Arc::new(FfmpegSegment {
+          path,
+          start_time: start_time + (start_seconds * 1000.0) as u128,
+          end_time: start_time + (end_seconds * 1000.0) as u128,
+          size,
+          capture_geometry: self.capture_geometry.clone(),
+          segment_dir: segment_dir.clone(),
+        }));
+      } else {
+        log::error!("Failed to parse segment list entry: {}", line);
+      }
+    }
+
+    Ok(segments)
+  }
+
+  // Waits until ffmpeg has finished writing the last segment and exited
+  pub async fn stop(&mut self) {
+    if let Some(stdin) = self.stdin.as_mut() {
+      if let Err(e) = stdin.write_all(b"q").await {
+        log::error!("Failed to stop ffmpeg process: {}", e);
+      }
+    }
+
+    if let Some(source) = self.source.as_mut() {
+      source.stop();
+    }
+
+    if (&mut self.exit_rx).await.is_err() {
+      log::error!("Ffmpeg log reader ended unexpectedly");
+    }
+  }
+}
+
+// A segment of a recording that has already been finished. Segments are shared between the
+// buffer and the Kaptures being assembled, so the file is kept until both are done with it.
+#[derive(Debug)]
+pub struct FfmpegSegment {
+  pub path: String,
+  pub start_time: u128,
+  pub end_time: u128,
+  // The size of the file in bytes
+  pub size: u64,
+  // The area of the screen that was recorded, which can differ between segments when
+  // following a window (only for video segments)
+  pub capture_geometry: Option<CaptureGeometry>,
+  // Keeps the directory around until the file has been removed
+  pub segment_dir: Arc<SegmentDir>,
+}
+
+impl Drop for FfmpegSegment {
+  // Removing the temporary file when the segment is cleaned up
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_file(&self.path) {
+      log::error!("{}", e);
+    }
+  }
+}
+
+// The temporary directory of a recording session. It's only removed once the session has
+// ended and every segment in it has been dropped, since the Kaptures being assembled can hold
+// on to segments after the buffer was cleared.
+#[derive(Debug)]
+pub struct SegmentDir {
+  pub path: String,
+}
+
+impl SegmentDir {
+  pub fn create(path: String) -> Result<Self, KaptError> {
+    fs::create_dir_all(&path)?;
+    Ok(Self { path })
+  }
+}
+
+impl Drop for SegmentDir {
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_dir_all(&self.path) {
+      log::error!("{}", e);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_start_time() {
+    assert_eq!(
+      parse_start_time("start: 1625151128.627318"),
+      Some(1_625_151_128_627)
+    );
+    assert_eq!(
+      parse_start_time("  Duration: N/A, start: 1625151128.627318, bitrate: N/A"),
+      Some(1_625_151_128_627)
+    );
+    assert_eq!(parse_start_time("Stream #0:0: Video: rawvideo"), None);
+  }
+}
@@ -0,0 +1,350 @@
+// Works out which parts of the buffered segments make up a Kapture. This only looks at the
+// segments' metadata, so it doesn't touch the files or ffmpeg.
+use std::sync::Arc;
+
+use crate::error::KaptError;
+use crate::state::FfmpegSegment;
+
+// The longest break between two segments of a stream that still counts as continuous footage.
+// Concatenating across a longer gap would shift everything before it out of sync.
+const MAX_GAP_MILLISECONDS: u128 = 250;
+
+// A segment as it's used in a Kapture; its end is cut off when the next segment of the same
+// stream starts before it ends (e.g. when the video stream was restarted to follow a window)
+#[derive(Debug, Clone)]
+pub struct PlannedSegment {
+  pub segment: Arc<FfmpegSegment>,
+  pub end_time: u128,
+}
+
+impl PlannedSegment {
+  pub fn duration(&self) -> u128 {
+    self.end_time.saturating_sub(self.segment.start_time)
+  }
+
+  // Where to stop reading the segment (in milliseconds from its start), if it's cut off
+  pub fn outpoint(&self) -> Option<u128> {
+    if self.end_time < self.segment.end_time {
+      Some(self.duration())
+    } else {
+      None
+    }
+  }
+}
+
+// The segments of one stream to concatenate, and where the Kapture starts in the result
+#[derive(Debug, Clone)]
+pub struct StreamPlan {
+  pub segments: Vec<PlannedSegment>,
+  // Milliseconds from the start of the concatenated segments
+  pub offset: u128,
+}
+
+impl StreamPlan {
+  // Keeps the segments overlapping the range from `start_time` to `end_time`. If the range
+  // starts in a gap between two segments, the one before the gap is kept as well so that the
+  // offset stays within the footage.
+  fn new(segments: Vec<PlannedSegment>, start_time: u128, end_time: u128) -> Self {
+    let first_index = segments
+      .iter()
+      .rposition(|planned| planned.segment.start_time <= start_time)
+      .unwrap_or(0);
+
+    let segments: Vec<PlannedSegment> = segments
+      .into_iter()
+      .skip(first_index)
+      .filter(|planned| planned.segment.start_time < end_time)
+      .collect();
+
+    let offset = segments.first().map_or(0, |first| {
+      start_time.saturating_sub(first.segment.start_time)
+    });
+
+    Self { segments, offset }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct KapturePlan {
+  pub video: StreamPlan,
+  pub audio: StreamPlan,
+  // The unix timestamp (in milliseconds) at which the Kapture actually starts
+  pub start_time: u128,
+  // In milliseconds
+  pub duration: u128,
+}
+
+// Returns the newest stretch of continuous footage of a stream, oldest first. Segments with an
+// invalid time range (e.g. from a clock that jumped backwards) are left out.
+fn continuous_segments(segments: &[Arc<FfmpegSegment>]) -> Vec<PlannedSegment> {
+  let mut segments: Vec<&Arc<FfmpegSegment>> = segments
+    .iter()
+    .filter(|segment| segment.end_time > segment.start_time)
+    .collect();
+  segments.sort_by_key(|segment| segment.start_time);
+
+  let mut planned_segments: Vec<PlannedSegment> = vec![];
+  for segment in segments {
+    if let Some(previous) = planned_segments.last_mut() {
+      if segment.start_time > previous.end_time + MAX_GAP_MILLISECONDS {
+        planned_segments.clear();
+      } else if segment.start_time < previous.end_time {
+        previous.end_time = segment.start_time;
+      }
+    }
+
+    planned_segments.push(PlannedSegment {
+      segment: segment.clone(),
+      end_time: segment.end_time,
+    });
+  }
+
+  // A segment starting at the same time as the next one is cut off entirely
+  planned_segments.retain(|planned| planned.duration() > 0);
+  planned_segments
+}
+
+// Plans a Kapture of the range from `start_time` to `end_time` (unix timestamps in
+// milliseconds). The range is shortened to the part that both streams cover, so a Kapture
+// longer than the buffer contains everything that's buffered.
+pub fn plan_kapture(
+  video_segments: &[Arc<FfmpegSegment>],
+  audio_segments: &[Arc<FfmpegSegment>],
+  start_time: u128,
+  end_time: u128,
+) -> Result<KapturePlan, KaptError> {
+  let video_segments = continuous_segments(video_segments);
+  let audio_segments = continuous_segments(audio_segments);
+
+  let (first_video_segment, last_video_segment) =
+    match (video_segments.first(), video_segments.last()) {
+      (Some(first), Some(last)) => (first, last),
+      _ => return Err(KaptError::NoSegments),
+    };
+  let (first_audio_segment, last_audio_segment) =
+    match (audio_segments.first(), audio_segments.last()) {
+      (Some(first), Some(last)) => (first, last),
+      _ => return Err(KaptError::NoSegments),
+    };
+
+  // Both streams have to cover the Kapture for the audio and video to stay in sync
+  let start_time = start_time
+    .max(first_video_segment.segment.start_time)
+    .max(first_audio_segment.segment.start_time);
+  let end_time = end_time
+    .min(last_video_segment.end_time)
+    .min(last_audio_segment.end_time);
+
+  if end_time <= start_time {
+    return Err(KaptError::NoSegments);
+  }
+
+  Ok(KapturePlan {
+    video: StreamPlan::new(video_segments, start_time, end_time),
+    audio: StreamPlan::new(audio_segments, start_time, end_time),
+    start_time,
+    duration: end_time - start_time,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state::SegmentDir;
+  use proptest::prelude::*;
+
+  // The segments don't point to files, so dropping them doesn't remove anything
+  fn segment(start_time: u128, end_time: u128) -> Arc<FfmpegSegment> {
+    Arc::new(FfmpegSegment {
+      path: String::new(),
+      start_time,
+      end_time,
+      size: 0,
+      capture_geometry: None,
+      segment_dir: Arc::new(SegmentDir {
+        path: String::new(),
+      }),
+    })
+  }
+
+  fn segments(times: &[(u128, u128)]) -> Vec<Arc<FfmpegSegment>> {
+    times
+      .iter()
+      .map(|(start_time, end_time)| segment(*start_time, *end_time))
+      .collect()
+  }
+
+  #[test]
+  fn restarts_after_gap() {
+    let gap_start = 10_000 + MAX_GAP_MILLISECONDS + 1;
+    let video = segments(&[(0, 5_000), (5_000, 10_000), (gap_start, gap_start + 5_000)]);
+    let audio = segments(&[(0, 20_000)]);
+
+    let plan = plan_kapture(&video, &audio, 0, 20_000).expect("Failed to plan Kapture");
+
+    assert_eq!(plan.start_time, gap_start);
+    assert_eq!(plan.duration, 5_000);
+    assert_eq!(plan.video.segments.len(), 1);
+    assert_eq!(plan.video.offset, 0);
+    assert_eq!(plan.audio.offset, gap_start);
+  }
+
+  #[test]
+  fn keeps_gaps_up_to_max_gap() {
+    let video = segments(&[(0, 5_000), (5_000 + MAX_GAP_MILLISECONDS, 10_000)]);
+
+    let planned_segments = continuous_segments(&video);
+
+    assert_eq!(planned_segments.len(), 2);
+  }
+
+  #[test]
+  fn cuts_overlapping_segments() {
+    let video = segments(&[(0, 5_000), (4_000, 9_000)]);
+
+    let planned_segments = continuous_segments(&video);
+
+    assert_eq!(planned_segments[0].end_time, 4_000);
+    assert_eq!(planned_segments[0].outpoint(), Some(4_000));
+    assert_eq!(planned_segments[1].end_time, 9_000);
+    assert_eq!(planned_segments[1].outpoint(), None);
+  }
+
+  #[test]
+  fn drops_segments_with_invalid_time_range() {
+    let video = segments(&[(5_000, 5_000), (0, 5_000), (9_000, 8_000), (5_000, 10_000)]);
+
+    let planned_segments = continuous_segments(&video);
+
+    let times: Vec<(u128, u128)> = planned_segments
+      .iter()
+      .map(|planned| (planned.segment.start_time, planned.end_time))
+      .collect();
+    assert_eq!(times, vec![(0, 5_000), (5_000, 10_000)]);
+  }
+
+  #[test]
+  fn clamps_range_to_buffer() {
+    let video = segments(&[(1_000, 6_000), (6_000, 11_000)]);
+    let audio = segments(&[(2_000, 7_000), (7_000, 10_500)]);
+
+    let plan = plan_kapture(&video, &audio, 0, 60_000).expect("Failed to plan Kapture");
+
+    assert_eq!(plan.start_time, 2_000);
+    assert_eq!(plan.duration, 8_500);
+    assert_eq!(plan.video.offset, 1_000);
+    assert_eq!(plan.audio.offset, 0);
+  }
+
+  #[test]
+  fn keeps_segment_before_gap_at_start() {
+    let video = segments(&[(0, 5_000), (5_200, 10_000)]);
+    let audio = segments(&[(0, 10_000)]);
+
+    let plan = plan_kapture(&video, &audio, 5_100, 5_150).expect("Failed to plan Kapture");
+
+    assert_eq!(plan.video.segments.len(), 1);
+    assert_eq!(plan.video.segments[0].segment.start_time, 0);
+    assert_eq!(plan.video.offset, 5_100);
+  }
+
+  #[test]
+  fn fails_without_segments() {
+    let video = segments(&[(0, 5_000)]);
+
+    assert!(matches!(
+      plan_kapture(&video, &[], 0, 5_000),
+      Err(KaptError::NoSegments)
+    ));
+    assert!(matches!(
+      plan_kapture(&video, &video, 6_000, 10_000),
+      Err(KaptError::NoSegments)
+    ));
+  }
+
+  // Segments starting anywhere in the first minute, some of which overlap, leave gaps or have
+  // an invalid time range (as from a clock jumping backwards)
+  fn arbitrary_segments() -> impl Strategy<Value = Vec<Arc<FfmpegSegment>>> {
+    prop::collection::vec((500u64..60_000, 0u64..7_000), 0..16).prop_map(|times| {
+      times
+        .into_iter()
+        .map(|(start_time, length)| {
+          // Lengths up to 500 make the segment end before it starts or where it starts
+          segment(start_time as u128, (start_time + length - 500) as u128)
+        })
+        .collect()
+    })
+  }
+
+  fn check_stream_plan(stream: &StreamPlan, start_time: u128, end_time: u128) {
+    let first = stream.segments.first().expect("Stream plan is empty");
+    let last = stream.segments.last().expect("Stream plan is empty");
+
+    // The offset is measured from the start of the first segment, which can't be after the
+    // start of the Kapture
+    assert!(first.segment.start_time <= start_time);
+    assert_eq!(stream.offset, start_time - first.segment.start_time);
+    // The range can end in a gap, which ffmpeg fills by stretching the stream
+    assert!(last.end_time + MAX_GAP_MILLISECONDS >= end_time);
+
+    for planned in &stream.segments {
+      assert!(planned.segment.end_time > planned.segment.start_time);
+      assert!(planned.duration() > 0);
+      assert!(planned.end_time <= planned.segment.end_time);
+      assert_eq!(
+        planned.outpoint(),
+        if planned.end_time < planned.segment.end_time {
+          Some(planned.duration())
+        } else {
+          None
+        }
+      );
+    }
+
+    for pair in stream.segments.windows(2) {
+      assert!(pair[0].end_time <= pair[1].segment.start_time);
+      assert!(pair[1].segment.start_time - pair[0].end_time <= MAX_GAP_MILLISECONDS);
+    }
+  }
+
+  proptest! {
+    #[test]
+    fn plans_stay_within_buffer_and_range(
+      video in arbitrary_segments(),
+      audio in arbitrary_segments(),
+      start_time in 0u64..70_000,
+      length in 0u64..70_000,
+    ) {
+      let start_time = start_time as u128;
+      let end_time = start_time + length as u128;
+
+      match plan_kapture(&video, &audio, start_time, end_time) {
+        Ok(plan) => {
+          let plan_end_time = plan.start_time + plan.duration;
+
+          prop_assert!(plan.duration > 0);
+          prop_assert!(plan.start_time >= start_time);
+          prop_assert!(plan_end_time <= end_time);
+
+          check_stream_plan(&plan.video, plan.start_time, plan_end_time);
+          check_stream_plan(&plan.audio, plan.start_time, plan_end_time);
+        }
+        Err(e) => prop_assert!(matches!(e, KaptError::NoSegments)),
+      }
+    }
+
+    #[test]
+    fn continuous_segments_have_no_overlaps_or_gaps(video in arbitrary_segments()) {
+      let planned_segments = continuous_segments(&video);
+
+      for planned in &planned_segments {
+        prop_assert!(planned.duration() > 0);
+      }
+
+      for pair in planned_segments.windows(2) {
+        prop_assert!(pair[0].end_time <= pair[1].segment.start_time);
+        prop_assert!(pair[1].segment.start_time - pair[0].end_time <= MAX_GAP_MILLISECONDS);
+      }
+    }
+  }
+}
@@ -0,0 +1,47 @@
+// Runs the whole record → Kapture flow on the test pattern backend, which only needs ffmpeg
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use kapt_core::backend::{CaptureBackendKind, SEGMENT_SECONDS};
+use kapt_core::{Recorder, RecorderConfig};
+
+fn has_ffmpeg() -> bool {
+  matches!(
+    Command::new("ffmpeg").arg("-version").output(),
+    Ok(output) if output.status.success()
+  )
+}
+
+#[tokio::test]
+async fn records_and_kaptures_test_pattern() {
+  if !has_ffmpeg() {
+    eprintln!("Skipping the test pattern recording since ffmpeg isn't installed");
+    return;
+  }
+
+  let video_folder = env::temp_dir().join(format!("kapt-test-{}", std::process::id()));
+  fs::create_dir_all(&video_folder).expect("Failed to create video folder");
+
+  let recorder = Recorder::new(RecorderConfig {
+    capture_backend: CaptureBackendKind::TestPattern,
+    video_folder: Some(video_folder.to_string_lossy().to_string()),
+    ..RecorderConfig::default()
+  });
+
+  recorder.activate().await.expect("Failed to activate");
+
+  // Lets ffmpeg finish the first segment
+  tokio::time::sleep(Duration::from_secs(SEGMENT_SECONDS as u64 + 1)).await;
+
+  let kapture = recorder.kapture(Duration::from_secs(3)).await;
+  recorder.deactivate().await.expect("Failed to deactivate");
+  let kapture = kapture.expect("Failed to create Kapture");
+
+  assert!(Path::new(&kapture.path).exists());
+  assert!(kapture.actual_milliseconds > 0);
+
+  fs::remove_dir_all(&video_folder).expect("Failed to remove video folder");
+}
@@ -0,0 +1,105 @@
+// Controls a running instance of Kapt through its control socket
+#[path = "../control.rs"]
+mod control;
+
+use control::{check_socket_dir, get_socket_path, ControlRequest, ControlResponse};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process;
+
+const USAGE: &str = "Usage: kaptctl <command>
+
+Commands:
+  activate                  Start recording into the buffer
+  deactivate                Stop recording and clear the buffer
+  kapture <seconds> [<after>]
+                            Save the last <seconds> seconds (and the next <after> seconds)
+                            and print the path of the video
+  status                    Print whether Kapt is recording and how much is buffered
+  set-audio-source <id>     Record from the PulseAudio source with the given index";
+
+fn parse_request(args: &[String]) -> Option<ControlRequest> {
+  let request = match (args.first()?.as_str(), args.get(1), args.get(2)) {
+    ("activate", None, None) => ControlRequest::Activate,
+    ("deactivate", None, None) => ControlRequest::Deactivate,
+    ("kapture", Some(seconds), seconds_after) => ControlRequest::Kapture {
+      seconds: seconds.parse().ok()?,
+      seconds_after: match seconds_after {
+        Some(seconds_after) => seconds_after.parse().ok()?,
+        None => 0,
+      },
+    },
+    ("status", None, None) => ControlRequest::Status,
+    ("set-audio-source", Some(audio_source), None) => ControlRequest::SetAudioSource {
+      audio_source: audio_source.parse().ok()?,
+    },
+    _ => return None,
+  };
+
+  if args.len() > 3 {
+    return None;
+  }
+
+  Some(request)
+}
+
+fn send_request(request: &ControlRequest) -> Result<ControlResponse, String> {
+  let socket_path = get_socket_path().map_err(|e| e.to_string())?;
+  let mut stream = check_socket_dir(&socket_path)
+    .and_then(|_| UnixStream::connect(&socket_path))
+    .map_err(|e| {
+      format!(
+        "Failed to connect to {} (is Kapt running?): {}",
+        socket_path.display(),
+        e
+      )
+    })?;
+
+  let mut request = serde_json::to_string(request).map_err(|e| e.to_string())?;
+  request.push('\n');
+  stream
+    .write_all(request.as_bytes())
+    .map_err(|e| e.to_string())?;
+
+  let mut response = String::new();
+  BufReader::new(stream)
+    .read_line(&mut response)
+    .map_err(|e| e.to_string())?;
+
+  serde_json::from_str(&response).map_err(|e| format!("Invalid response from Kapt: {}", e))
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+
+  let request = match parse_request(&args) {
+    Some(request) => request,
+    None => {
+      eprintln!("{}", USAGE);
+      process::exit(2);
+    }
+  };
+
+  match send_request(&request) {
+    Ok(ControlResponse::Done) => {}
+    Ok(ControlResponse::KaptureCreated { path }) => println!("{}", path),
+    Ok(ControlResponse::Status(status)) => {
+      println!("active: {}", status.active);
+      println!("audio source: {}", status.audio_source);
+      println!(
+        "buffered: {:.1}s ({:.1} MB)",
+        status.cached_milliseconds as f64 / 1000.0,
+        status.cached_bytes as f64 / (1024.0 * 1024.0)
+      );
+    }
+    Ok(ControlResponse::Error { message, .. }) => {
+      eprintln!("{}", message);
+      process::exit(1);
+    }
+    Err(e) => {
+      eprintln!("{}", e);
+      process::exit(1);
+    }
+  }
+}
@@ -0,0 +1,83 @@
+// The protocol spoken over Kapt's control socket. Every connection sends one request as a line
+// of JSON and receives one response the same way. This module is shared with `kaptctl`, so it
+// can't depend on the rest of the app.
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+  Activate,
+  Deactivate,
+  Kapture {
+    seconds: u32,
+    #[serde(default)]
+    seconds_after: u32,
+  },
+  Status,
+  SetAudioSource {
+    audio_source: usize,
+  },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KaptStatus {
+  pub active: bool,
+  pub audio_source: usize,
+  // How much footage is in the buffer
+  pub cached_milliseconds: u64,
+  pub cached_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlResponse {
+  Done,
+  KaptureCreated { path: String },
+  Status(KaptStatus),
+  Error { kind: String, message: String },
+}
+
+// `$XDG_RUNTIME_DIR/kapt.sock`, which is only accessible to the current user. Without a runtime
+// directory, the socket goes into a `kapt-<uid>` directory in the temporary directory instead,
+// which is made private when Kapt starts listening.
+pub fn get_socket_path() -> io::Result<PathBuf> {
+  match env::var_os("XDG_RUNTIME_DIR") {
+    Some(runtime_dir) if !runtime_dir.is_empty() => {
+      Ok(PathBuf::from(runtime_dir).join("kapt.sock"))
+    }
+    _ => Ok(
+      env::temp_dir()
+        .join(format!("kapt-{}", get_uid()?))
+        .join("kapt.sock"),
+    ),
+  }
+}
+
+// The owner of `/proc/self` is the user the process runs as
+fn get_uid() -> io::Result<u32> {
+  Ok(fs::metadata("/proc/self")?.uid())
+}
+
+// Checks that only the current user can access the directory of the socket, since anyone else
+// could otherwise send commands to Kapt or pose as it
+pub fn check_socket_dir(socket_path: &Path) -> io::Result<()> {
+  let socket_dir = socket_path.parent().unwrap_or_else(|| Path::new("/"));
+  let metadata = fs::symlink_metadata(socket_dir)?;
+
+  if !metadata.is_dir() || metadata.uid() != get_uid()? || metadata.mode() & 0o077 != 0 {
+    return Err(io::Error::new(
+      io::ErrorKind::PermissionDenied,
+      format!(
+        "{} has to be a directory that only the current user can access.",
+        socket_dir.display()
+      ),
+    ));
+  }
+
+  Ok(())
+}
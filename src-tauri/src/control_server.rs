@@ -0,0 +1,134 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use kapt_core::utils::get_current_time;
+use kapt_core::KaptError;
+
+use crate::control::{
+  check_socket_dir, get_socket_path, ControlRequest, ControlResponse, KaptStatus,
+};
+use crate::kapt::{self, RECORDER};
+
+async fn handle_request(request: ControlRequest) -> ControlResponse {
+  let result = match request {
+    ControlRequest::Activate => kapt::set_kapt_activation(true)
+      .await
+      .map(|_| ControlResponse::Done),
+    ControlRequest::Deactivate => kapt::set_kapt_activation(false)
+      .await
+      .map(|_| ControlResponse::Done),
+    ControlRequest::Kapture {
+      seconds,
+      seconds_after,
+    } => kapt::create_kapture(get_current_time(), seconds, seconds_after, None)
+      .await
+      .map(|kapture| ControlResponse::KaptureCreated { path: kapture.path }),
+    ControlRequest::Status => get_status().map(ControlResponse::Status),
+    ControlRequest::SetAudioSource { audio_source } => {
+      kapt::set_audio_source(audio_source).map(|_| ControlResponse::Done)
+    }
+  };
+
+  result.unwrap_or_else(|e| ControlResponse::Error {
+    kind: e.kind().to_string(),
+    message: e.to_string(),
+  })
+}
+
+fn get_status() -> Result<KaptStatus, KaptError> {
+  let buffer_status = RECORDER.buffer_status()?;
+
+  Ok(KaptStatus {
+    active: RECORDER.is_active()?,
+    audio_source: RECORDER.config()?.audio_source,
+    cached_milliseconds: buffer_status.cached_milliseconds,
+    cached_bytes: buffer_status.cached_bytes,
+  })
+}
+
+async fn handle_connection(stream: UnixStream) -> Result<(), KaptError> {
+  let (reader, mut writer) = stream.into_split();
+
+  let mut line = String::new();
+  BufReader::new(reader).read_line(&mut line).await?;
+
+  let response = match serde_json::from_str::<ControlRequest>(&line) {
+    Ok(request) => handle_request(request).await,
+    Err(e) => ControlResponse::Error {
+      kind: "invalid_request".to_string(),
+      message: e.to_string(),
+    },
+  };
+
+  let mut response = serde_json::to_string(&response).map_err(|e| KaptError::Io(e.to_string()))?;
+  response.push('\n');
+  writer.write_all(response.as_bytes()).await?;
+
+  Ok(())
+}
+
+// Set once this instance is listening on the socket, since only then is it the one to remove
+// it (another instance might own it instead)
+static IS_SOCKET_BOUND: AtomicBool = AtomicBool::new(false);
+
+// Creates the directory of the socket if it doesn't exist yet and checks that it's private
+fn create_socket_dir(socket_path: &Path) -> io::Result<()> {
+  if let Some(socket_dir) = socket_path.parent() {
+    match fs::DirBuilder::new().mode(0o700).create(socket_dir) {
+      Ok(()) => {}
+      Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+      Err(e) => return Err(e),
+    }
+  }
+
+  check_socket_dir(socket_path)
+}
+
+// Listens for `kaptctl` commands until Kapt exits
+pub async fn serve_control_socket() -> Result<(), KaptError> {
+  let socket_path = get_socket_path()?;
+  create_socket_dir(&socket_path)?;
+
+  if socket_path.exists() {
+    // Only one instance of Kapt can own the socket
+    if UnixStream::connect(&socket_path).await.is_ok() {
+      return Err(KaptError::Io(format!(
+        "Another instance of Kapt is already listening on {}.",
+        socket_path.display()
+      )));
+    }
+
+    // Left behind by an instance that didn't exit cleanly
+    fs::remove_file(&socket_path)?;
+  }
+
+  // The socket is only reachable through its private directory, so it doesn't have to be
+  // locked down after binding
+  let listener = UnixListener::bind(&socket_path)?;
+  IS_SOCKET_BOUND.store(true, Ordering::SeqCst);
+
+  loop {
+    let (stream, _) = listener.accept().await?;
+
+    tauri::async_runtime::spawn(async move {
+      if let Err(e) = handle_connection(stream).await {
+        RECORDER.report_error(e);
+      }
+    });
+  }
+}
+
+pub fn remove_control_socket() {
+  if !IS_SOCKET_BOUND.swap(false, Ordering::SeqCst) {
+    return;
+  }
+
+  if let Err(e) = get_socket_path().and_then(fs::remove_file) {
+    log::error!("Failed to remove the control socket: {}", e);
+  }
+}
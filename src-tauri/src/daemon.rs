@@ -0,0 +1,65 @@
+// Runs the rolling buffer without a window. Kapt is then controlled through `kaptctl` or
+// signals:
+//   SIGUSR1 - Kaptures the last `SIGNAL_KAPTURE_SECONDS` seconds
+//   SIGUSR2 - Toggles the recording
+//   SIGINT/SIGTERM - Stops the recording and exits
+use kapt_core::KaptEvent;
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::control_server;
+use crate::kapt::{self, RECORDER};
+
+const SIGNAL_KAPTURE_SECONDS: u32 = 30;
+
+// Logs the events to the terminal since there is no window to show them in
+async fn print_events(mut events: Receiver<KaptEvent>) {
+  loop {
+    match events.recv().await {
+      Ok(KaptEvent::ActivationToggled(active)) => {
+        println!("{}", if active { "Activated" } else { "Deactivated" })
+      }
+      Ok(KaptEvent::KaptureProgress(_)) => {}
+      Ok(KaptEvent::KaptureCreated(kapture)) => println!("Kapture created: {}", kapture.path),
+      Ok(KaptEvent::MarkersChanged(_)) => {}
+      Ok(KaptEvent::Error(error)) => eprintln!("Error: {}", error),
+      Err(RecvError::Lagged(_)) => continue,
+      Err(RecvError::Closed) => break,
+    }
+  }
+}
+
+pub fn run_daemon() {
+  let mut signals =
+    Signals::new([SIGINT, SIGTERM, SIGUSR1, SIGUSR2]).expect("Failed to register signal handlers");
+
+  tauri::async_runtime::spawn(print_events(RECORDER.subscribe_events()));
+
+  tauri::async_runtime::spawn(async {
+    if let Err(e) = control_server::serve_control_socket().await {
+      RECORDER.report_error(e);
+    }
+  });
+
+  if let Err(e) = tauri::async_runtime::block_on(kapt::set_kapt_activation(true)) {
+    eprintln!("Failed to start the recording: {}", e);
+    control_server::remove_control_socket();
+    std::process::exit(1);
+  }
+
+  for signal in signals.forever() {
+    match signal {
+      SIGUSR1 => kapt::make_kapture(SIGNAL_KAPTURE_SECONDS, 0),
+      SIGUSR2 => kapt::toggle_kapt_activation(),
+      _ => break,
+    }
+  }
+
+  // Removes the segments of the buffer
+  if let Err(e) = tauri::async_runtime::block_on(kapt::set_kapt_activation(false)) {
+    eprintln!("Failed to stop the recording: {}", e);
+  }
+
+  control_server::remove_control_socket();
+}
@@ -0,0 +1,137 @@
+use kapt_core::markers::MarkerKind;
+use kapt_core::KaptError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::{AppHandle, GlobalShortcutManager, Manager};
+
+// What a global shortcut does; the same actions are available in the tray menu
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HotkeyAction {
+  ToggleActivation,
+  Kapture {
+    seconds: u32,
+    // Keeps recording for this long after the shortcut is pressed
+    #[serde(default)]
+    seconds_after: u32,
+  },
+  // Marks the moment the shortcut is pressed, to export a range between markers later
+  AddMarker {
+    kind: MarkerKind,
+  },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HotkeyBinding {
+  // A Tauri accelerator, e.g. "Super+Shift+5"
+  pub accelerator: String,
+  pub action: HotkeyAction,
+}
+
+pub fn default_hotkeys() -> Vec<HotkeyBinding> {
+  vec![
+    HotkeyBinding {
+      accelerator: "Super+Shift+5".to_string(),
+      action: HotkeyAction::Kapture {
+        seconds: 5,
+        seconds_after: 0,
+      },
+    },
+    HotkeyBinding {
+      accelerator: "Super+Shift+K".to_string(),
+      action: HotkeyAction::ToggleActivation,
+    },
+  ]
+}
+
+// Brings an accelerator into a canonical form so that e.g. "shift+super+5" and "Super+Shift+5"
+// are detected as the same shortcut
+fn normalize_accelerator(accelerator: &str) -> Result<String, KaptError> {
+  const MODIFIERS: [&str; 4] = ["ctrl", "alt", "shift", "super"];
+
+  let mut modifiers = vec![];
+  let mut keys = vec![];
+  for part in accelerator.split('+') {
+    let part = part.trim().to_lowercase();
+    let part = match part.as_str() {
+      "control" | "cmdorctrl" | "commandorcontrol" => "ctrl".to_string(),
+      "option" => "alt".to_string(),
+      "cmd" | "command" | "meta" => "super".to_string(),
+      _ => part,
+    };
+
+    if MODIFIERS.contains(&part.as_str()) {
+      modifiers.push(part);
+    } else {
+      keys.push(part);
+    }
+  }
+
+  if keys.len() != 1 || keys[0].is_empty() {
+    return Err(KaptError::InvalidSettings(format!(
+      "\"{}\" must consist of modifiers and exactly one key.",
+      accelerator
+    )));
+  }
+
+  modifiers.sort_by_key(|modifier| MODIFIERS.iter().position(|m| m == modifier));
+  modifiers.dedup();
+  modifiers.push(keys.remove(0));
+
+  Ok(modifiers.join("+"))
+}
+
+// Checks that every binding is well-formed and that no shortcut is bound twice
+pub fn validate_hotkeys(hotkeys: &[HotkeyBinding]) -> Result<(), KaptError> {
+  let mut accelerators: HashMap<String, &HotkeyBinding> = HashMap::new();
+
+  for hotkey in hotkeys {
+    if let HotkeyAction::Kapture {
+      seconds: 0,
+      seconds_after: 0,
+    } = hotkey.action
+    {
+      return Err(KaptError::InvalidSettings(
+        "A Kapture has to be at least one second long.".to_string(),
+      ));
+    }
+
+    let accelerator = normalize_accelerator(&hotkey.accelerator)?;
+    if let Some(other_hotkey) = accelerators.insert(accelerator, hotkey) {
+      return Err(KaptError::HotkeyConflict {
+        accelerator: hotkey.accelerator.clone(),
+        message: format!("It's also bound as \"{}\".", other_hotkey.accelerator),
+      });
+    }
+  }
+
+  Ok(())
+}
+
+// Replaces all of Kapt's global shortcuts with `hotkeys`. Fails if a shortcut is already taken
+// by another application, in which case the shortcuts registered so far stay registered.
+pub fn register_hotkeys(
+  app: &AppHandle,
+  hotkeys: &[HotkeyBinding],
+  on_action: fn(HotkeyAction),
+) -> Result<(), KaptError> {
+  validate_hotkeys(hotkeys)?;
+
+  let mut shortcut_manager = app.global_shortcut_manager();
+  shortcut_manager
+    .unregister_all()
+    .map_err(|e| KaptError::InvalidSettings(e.to_string()))?;
+
+  for hotkey in hotkeys {
+    let action = hotkey.action;
+
+    shortcut_manager
+      .register(&hotkey.accelerator, move || on_action(action))
+      .map_err(|e| KaptError::HotkeyConflict {
+        accelerator: hotkey.accelerator.clone(),
+        message: e.to_string(),
+      })?;
+  }
+
+  Ok(())
+}
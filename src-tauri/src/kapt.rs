@@ -0,0 +1,150 @@
+// The operations shared by every client of Kapt (the window, the tray, global shortcuts, the
+// control socket and the daemon). Their outcomes are published as `KaptEvent`s by the
+// recorder so that each client can reflect changes made by the others.
+use kapt_core::encoding::KaptureFormat;
+use kapt_core::library::{KaptureEntry, KaptureLibrary};
+use kapt_core::markers::MarkerKind;
+use kapt_core::{KaptError, Recorder, RecorderConfig};
+use lazy_static::lazy_static;
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::hotkeys::HotkeyBinding;
+use crate::settings::{self, Settings};
+
+lazy_static! {
+  // Restored from the previous run of Kapt
+  static ref SAVED_SETTINGS: Settings = settings::load_settings();
+
+  // Every Kapture made by this or a previous run of Kapt
+  pub static ref LIBRARY: Arc<KaptureLibrary> = Arc::new(KaptureLibrary::new(get_library_path()));
+
+  pub static ref RECORDER: Recorder =
+    Recorder::new(SAVED_SETTINGS.recorder_config()).with_library(LIBRARY.clone());
+
+  // The global shortcuts for making Kaptures and toggling the recording
+  pub static ref HOTKEYS: RwLock<Vec<HotkeyBinding>> = RwLock::new(SAVED_SETTINGS.hotkeys.clone());
+}
+
+// `$XDG_DATA_HOME/kapt/library.jsonl`, falling back to `~/.local/share` and then the temporary
+// directory
+fn get_library_path() -> PathBuf {
+  let data_dir = match (env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+    (Some(data_dir), _) if !data_dir.is_empty() => PathBuf::from(data_dir),
+    (_, Some(home_dir)) => PathBuf::from(home_dir).join(".local").join("share"),
+    _ => env::temp_dir(),
+  };
+
+  data_dir.join("kapt").join("library.jsonl")
+}
+
+pub fn save_settings() -> Result<(), KaptError> {
+  let config = RECORDER.config()?;
+  let hotkeys = HOTKEYS.read()?;
+  settings::save_settings(&Settings::new(&config, &hotkeys))
+}
+
+// Changes the recorder's configuration and saves it
+pub fn update_config(
+  update: impl FnOnce(&mut RecorderConfig),
+) -> Result<RecorderConfig, KaptError> {
+  let config = RECORDER.update_config(update)?;
+  save_settings()?;
+  Ok(config)
+}
+
+pub async fn set_kapt_activation(active: bool) -> Result<(), KaptError> {
+  if active {
+    RECORDER.activate().await
+  } else {
+    RECORDER.deactivate().await
+  }
+}
+
+// Activates Kapt if it's inactive and deactivates it otherwise
+pub fn toggle_kapt_activation() {
+  let is_active = match RECORDER.is_active() {
+    Ok(is_active) => is_active,
+    Err(e) => {
+      RECORDER.report_error(e);
+      return;
+    }
+  };
+
+  tauri::async_runtime::spawn(async move {
+    if let Err(e) = set_kapt_activation(!is_active).await {
+      RECORDER.report_error(e);
+    }
+  });
+}
+
+// Kaptures the `seconds` seconds before `timestamp` (in milliseconds) and the
+// `seconds_after` seconds after it, in the configured format unless `format` is given
+pub async fn create_kapture(
+  timestamp: u128,
+  seconds: u32,
+  seconds_after: u32,
+  format: Option<KaptureFormat>,
+) -> Result<KaptureEntry, KaptError> {
+  let format = match format {
+    Some(format) => format,
+    None => RECORDER.config()?.kapture_format,
+  };
+
+  if timestamp > kapt_core::utils::get_current_time() {
+    return Err(KaptError::InvalidSettings(
+      "A Kapture can't be requested for a time in the future.".to_string(),
+    ));
+  }
+
+  // The recorder waits until the end of the Kapture has been recorded
+  let end_time = timestamp
+    .checked_add(seconds_after as u128 * 1000)
+    .ok_or_else(|| {
+      KaptError::InvalidSettings("The Kapture ends too far in the future.".to_string())
+    })?;
+  let duration = Duration::from_secs(seconds as u64 + seconds_after as u64);
+
+  RECORDER.kapture_at(end_time, duration, format).await
+}
+
+// Kaptures the last `seconds` seconds and the next `seconds_after` seconds in the background
+pub fn make_kapture(seconds: u32, seconds_after: u32) {
+  // The Kapture ends when it was requested, not when the task gets to run
+  let timestamp = kapt_core::utils::get_current_time();
+  tauri::async_runtime::spawn(async move {
+    if let Err(e) = create_kapture(timestamp, seconds, seconds_after, None).await {
+      RECORDER.report_error(e);
+    }
+  });
+}
+
+// Saves the footage between two unix timestamps (in milliseconds), in the configured format
+// unless `format` is given
+pub async fn export_range(
+  start_time: u128,
+  end_time: u128,
+  format: Option<KaptureFormat>,
+) -> Result<KaptureEntry, KaptError> {
+  let format = match format {
+    Some(format) => format,
+    None => RECORDER.config()?.kapture_format,
+  };
+
+  RECORDER.export_range(start_time, end_time, format).await
+}
+
+// Adds an unlabelled marker at the current time
+pub fn mark_moment(kind: MarkerKind) {
+  let timestamp = kapt_core::utils::get_current_time();
+  if let Err(e) = RECORDER.add_marker(timestamp, kind, None) {
+    RECORDER.report_error(e);
+  }
+}
+
+pub fn set_audio_source(audio_source: usize) -> Result<(), KaptError> {
+  update_config(|config| config.audio_source = audio_source)?;
+  Ok(())
+}
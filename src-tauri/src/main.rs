@@ -3,50 +3,173 @@
   windows_subsystem = "windows"
 )]
 
-mod audio;
-mod kapture;
-mod recording;
-mod state;
-mod utils;
+mod control;
+mod control_server;
+mod daemon;
+mod hotkeys;
+mod kapt;
+mod settings;
 
-use audio::AudioSource;
+use hotkeys::{HotkeyAction, HotkeyBinding};
+use kapt::{HOTKEYS, LIBRARY, RECORDER};
+use kapt_core::audio::{self, AudioSource};
+use kapt_core::backend::CaptureBackendKind;
+use kapt_core::display::{self, CaptureGeometry, CaptureMode, CaptureRegion, Monitor, Window};
+use kapt_core::encoding::{self, EncodingProfile, KaptureFormat, KAPTURE_FORMATS};
+use kapt_core::events::KaptureStage;
+use kapt_core::library::KaptureEntry;
+use kapt_core::markers::{Marker, MarkerKind};
+use kapt_core::recording::BufferStatus;
+use kapt_core::{KaptError, KaptEvent};
 use lazy_static::lazy_static;
-use state::KaptState;
-use std::{path::PathBuf, sync::RwLock};
+use std::convert::TryFrom;
+use std::{collections::HashMap, env, path::PathBuf, sync::Mutex};
 lazy_static! {
-  static ref KAPT_STATE: RwLock<KaptState> = RwLock::new(KaptState::new());
+  // Needed to register global shortcuts from commands
+  static ref APP_HANDLE: Mutex<Option<tauri::AppHandle>> = Mutex::new(None);
+  // The percentage of each Kapture that's being made, by ID, since several can be made at once
+  static ref KAPTURE_PERCENTS: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
+}
+
+// The frontend sends numbers as signed integers, which are checked before they're used as
+// times or durations
+fn to_unsigned<T: TryFrom<i64>>(name: &str, value: i64) -> Result<T, KaptError> {
+  T::try_from(value)
+    .map_err(|_| KaptError::InvalidSettings(format!("{} can't be {}.", name, value)))
 }
 
 #[tauri::command]
-async fn deactivate_kapt() {
-  recording::deactivate_kapt(&*KAPT_STATE).await;
+async fn deactivate_kapt() -> Result<(), KaptError> {
+  kapt::set_kapt_activation(false).await
 }
 
 #[tauri::command]
-async fn activate_kapt() {
-  recording::activate_kapt(&*KAPT_STATE).await;
+async fn activate_kapt() -> Result<(), KaptError> {
+  kapt::set_kapt_activation(true).await
 }
 
 #[tauri::command]
 // timestamp - Unix timestamp of when the user pressed the Kapture button (in seconds)
-async fn create_kapture(timestamp: i64, seconds_to_capture: i64) -> String {
-  kapture::create_kapture(&*KAPT_STATE, timestamp as u128, seconds_to_capture as u32).await
+// seconds_after - How long to keep recording after the button was pressed
+// format - The format to save the Kapture in, or `None` for the configured one
+// Returns where the video and its previews were saved
+async fn create_kapture(
+  timestamp: i64,
+  seconds_to_capture: i64,
+  seconds_after: Option<i64>,
+  format: Option<KaptureFormat>,
+) -> Result<KaptureEntry, KaptError> {
+  kapt::create_kapture(
+    to_unsigned("The timestamp", timestamp)?,
+    to_unsigned("The Kapture length", seconds_to_capture)?,
+    to_unsigned("The time after the Kapture", seconds_after.unwrap_or(0))?,
+    format,
+  )
+  .await
+}
+
+#[tauri::command]
+fn get_kapture_format() -> Result<KaptureFormat, KaptError> {
+  Ok(RECORDER.config()?.kapture_format)
+}
+
+#[tauri::command]
+fn set_kapture_format(format: KaptureFormat) -> Result<(), KaptError> {
+  kapt::update_config(|config| config.kapture_format = format)?;
+
+  if let Some(app_handle) = APP_HANDLE.lock()?.as_ref() {
+    update_kapture_format_menu(app_handle, format);
+  }
+
+  Ok(())
+}
+
+#[tauri::command]
+// kapture_id - The Kapture from the `kapture_progress` events, or `None` to cancel every
+// Kapture that's being made
+fn cancel_kapture(kapture_id: Option<String>) -> Result<(), KaptError> {
+  RECORDER.cancel_kapture(kapture_id.as_deref())
+}
+
+#[tauri::command]
+// timestamp - Unix timestamp of the moment to mark (in milliseconds)
+fn add_marker(
+  timestamp: i64,
+  kind: MarkerKind,
+  label: Option<String>,
+) -> Result<Marker, KaptError> {
+  RECORDER.add_marker(to_unsigned("The timestamp", timestamp)?, kind, label)
+}
+
+#[tauri::command]
+fn remove_marker(marker_id: String) -> Result<(), KaptError> {
+  RECORDER.remove_marker(&marker_id)
+}
+
+#[tauri::command]
+fn get_markers() -> Result<Vec<Marker>, KaptError> {
+  RECORDER.markers()
+}
+
+#[tauri::command]
+// start_ms, end_ms - Unix timestamps of the range to save, e.g. the times of two markers
+// format - The format to save the range in, or `None` for the configured one
+async fn export_range(
+  start_ms: i64,
+  end_ms: i64,
+  format: Option<KaptureFormat>,
+) -> Result<KaptureEntry, KaptError> {
+  kapt::export_range(
+    to_unsigned("The start of the range", start_ms)?,
+    to_unsigned("The end of the range", end_ms)?,
+    format,
+  )
+  .await
+}
+
+#[tauri::command]
+// The Kaptures in the library, newest first
+fn list_kaptures() -> Result<Vec<KaptureEntry>, KaptError> {
+  LIBRARY.list()
+}
+
+#[tauri::command]
+// query - Matched against the titles, tags and file names, ignoring case
+fn search_kaptures(query: String) -> Result<Vec<KaptureEntry>, KaptError> {
+  LIBRARY.search(&query)
+}
+
+#[tauri::command]
+// title - The new title, or `None` to show the file name instead
+fn rename_kapture(kapture_id: String, title: Option<String>) -> Result<KaptureEntry, KaptError> {
+  LIBRARY.rename(&kapture_id, title)
+}
+
+#[tauri::command]
+// tags - Replaces all of the Kapture's tags
+fn tag_kapture(kapture_id: String, tags: Vec<String>) -> Result<KaptureEntry, KaptError> {
+  LIBRARY.set_tags(&kapture_id, tags)
 }
 
 #[tauri::command]
-fn set_audio_source(audio_source: usize) {
-  let mut state = &mut *KAPT_STATE.write().expect("Failed to get write lock");
-  state.audio_source = audio_source;
+// Deletes the video as well
+fn delete_kapture(kapture_id: String) -> Result<(), KaptError> {
+  LIBRARY.delete(&kapture_id)
 }
 
 #[tauri::command]
-fn set_video_folder(video_folder: String) {
-  let mut state = &mut *KAPT_STATE.write().expect("Failed to get write lock");
-  state.video_folder = Some(video_folder);
+fn set_audio_source(audio_source: usize) -> Result<(), KaptError> {
+  kapt::set_audio_source(audio_source)
 }
 
 #[tauri::command]
-fn get_audio_sources() -> Vec<AudioSource> {
+fn set_video_folder(video_folder: String) -> Result<(), KaptError> {
+  kapt::update_config(|config| config.video_folder = Some(video_folder))?;
+  Ok(())
+}
+
+#[tauri::command]
+fn get_audio_sources() -> Result<Vec<AudioSource>, KaptError> {
   audio::get_audio_sources()
 }
 
@@ -58,9 +181,134 @@ fn select_video_folder() -> Option<PathBuf> {
 }
 
 #[tauri::command]
-fn set_max_seconds_cached(seconds: u32) {
-  let mut state = &mut *KAPT_STATE.write().expect("Failed to get write lock");
-  state.max_seconds_cached = seconds;
+fn set_max_seconds_cached(seconds: u32) -> Result<(), KaptError> {
+  kapt::update_config(|config| config.max_seconds_cached = seconds)?;
+  Ok(())
+}
+
+#[tauri::command]
+// megabytes - The maximum disk space of the buffer, or `None` for no limit
+fn set_max_cache_megabytes(megabytes: Option<u32>) -> Result<(), KaptError> {
+  kapt::update_config(|config| config.max_cache_megabytes = megabytes)?;
+  Ok(())
+}
+
+#[tauri::command]
+fn get_buffer_status() -> Result<BufferStatus, KaptError> {
+  RECORDER.buffer_status()
+}
+
+#[tauri::command]
+fn get_capture_backend() -> Result<CaptureBackendKind, KaptError> {
+  Ok(RECORDER.config()?.capture_backend)
+}
+
+#[tauri::command]
+// capture_backend - Takes effect the next time Kapt is activated
+fn set_capture_backend(capture_backend: CaptureBackendKind) -> Result<(), KaptError> {
+  RECORDER.update_config(|config| config.capture_backend = capture_backend)?;
+  Ok(())
+}
+
+#[tauri::command]
+fn get_capture_mode() -> Result<CaptureMode, KaptError> {
+  Ok(RECORDER.config()?.capture_mode)
+}
+
+#[tauri::command]
+fn get_capture_geometry() -> Result<CaptureGeometry, KaptError> {
+  let config = RECORDER.config()?;
+
+  config
+    .capture_backend
+    .backend()
+    .resolve_capture_geometry(&config.capture_mode)
+    .ok_or(KaptError::CaptureGeometryUnavailable)
+}
+
+#[tauri::command]
+fn get_monitors() -> Vec<Monitor> {
+  display::get_monitors()
+}
+
+#[tauri::command]
+// monitor - The name of the X output to capture, or `None` to capture all monitors
+fn set_capture_monitor(monitor: Option<String>) -> Result<(), KaptError> {
+  let capture_mode = match monitor {
+    Some(name) => CaptureMode::Monitor { name },
+    None => CaptureMode::Screen,
+  };
+
+  kapt::update_config(|config| config.capture_mode = capture_mode)?;
+  Ok(())
+}
+
+#[tauri::command]
+// region - The rectangle to capture, or `None` to capture the whole screen
+fn set_capture_region(region: Option<CaptureRegion>) -> Result<(), KaptError> {
+  let capture_mode = match region {
+    Some(region) => {
+      let screen_geometry =
+        display::get_screen_geometry().ok_or(KaptError::CaptureGeometryUnavailable)?;
+      region
+        .validate(&screen_geometry)
+        .map_err(KaptError::InvalidSettings)?;
+      CaptureMode::Region(region)
+    }
+    None => CaptureMode::Screen,
+  };
+
+  kapt::update_config(|config| config.capture_mode = capture_mode)?;
+  Ok(())
+}
+
+#[tauri::command]
+fn select_capture_region() -> Option<CaptureRegion> {
+  display::select_capture_region()
+}
+
+#[tauri::command]
+fn get_windows() -> Vec<Window> {
+  display::get_windows()
+}
+
+#[tauri::command]
+// window_id - The X11 window to follow, or `None` to capture the whole screen
+fn set_capture_window(window_id: Option<u32>) -> Result<(), KaptError> {
+  let capture_mode = match window_id {
+    Some(id) => CaptureMode::Window { id },
+    None => CaptureMode::Screen,
+  };
+
+  kapt::update_config(|config| config.capture_mode = capture_mode)?;
+  Ok(())
+}
+
+#[tauri::command]
+fn get_encoding_profile() -> Result<EncodingProfile, KaptError> {
+  Ok(RECORDER.config()?.encoding_profile)
+}
+
+#[tauri::command]
+fn set_encoding_profile(encoding_profile: EncodingProfile) -> Result<(), KaptError> {
+  kapt::update_config(|config| config.encoding_profile = encoding_profile)?;
+  Ok(())
+}
+
+#[tauri::command]
+fn get_encoding_preset_names() -> Vec<&'static str> {
+  encoding::ENCODING_PRESET_NAMES.to_vec()
+}
+
+#[tauri::command]
+// preset - One of "low_cpu", "balanced" or "archival"
+fn set_encoding_preset(preset: String) -> Result<EncodingProfile, KaptError> {
+  let encoding_profile = encoding::get_encoding_preset(&preset).ok_or_else(|| {
+    KaptError::InvalidSettings(format!("Unknown encoding preset \"{}\".", preset))
+  })?;
+
+  let config = kapt::update_config(|config| config.encoding_profile = encoding_profile)?;
+  Ok(config.encoding_profile)
 }
 
 use tauri::{
@@ -68,15 +316,168 @@ use tauri::{
   SystemTraySubmenu,
 };
 
-use crate::utils::get_current_time;
+use tokio::sync::broadcast::error::RecvError;
+
+// The durations offered in the tray's Kapture submenu
+const KAPTURE_SECONDS_OPTIONS: [u32; 5] = [5, 10, 15, 30, 60];
+// The seconds before and after the click offered for Kaptures around a moment
+const KAPTURE_AROUND_OPTIONS: [(u32, u32); 2] = [(15, 15), (30, 30)];
+
+fn get_kapture_around_id(seconds: u32, seconds_after: u32) -> String {
+  format!("kapture_around_{}_{}", seconds, seconds_after)
+}
+
+fn toggle_kapture_menu_activation(app: &tauri::AppHandle, enabled: bool) {
+  let item_ids = KAPTURE_SECONDS_OPTIONS
+    .iter()
+    .map(|seconds_option| format!("kapture_seconds_{}", seconds_option))
+    .chain(
+      KAPTURE_AROUND_OPTIONS
+        .iter()
+        .map(|(seconds, seconds_after)| get_kapture_around_id(*seconds, *seconds_after)),
+    );
+
+  for item_id in item_ids {
+    app
+      .tray_handle()
+      .get_item(&item_id)
+      .set_enabled(enabled)
+      .expect("Failed to set enabled");
+  }
+}
+
+fn get_kapture_format_title(format: KaptureFormat, selected: KaptureFormat) -> String {
+  let name = format.extension().to_uppercase();
+
+  if format == selected {
+    format!("✓ {}", name)
+  } else {
+    name
+  }
+}
+
+// Marks the selected format in the tray's Format submenu
+fn update_kapture_format_menu(app: &tauri::AppHandle, selected: KaptureFormat) {
+  for format in &KAPTURE_FORMATS {
+    app
+      .tray_handle()
+      .get_item(&format!("kapture_format_{}", format.extension()))
+      .set_title(get_kapture_format_title(*format, selected))
+      .expect("Failed to set menu title");
+  }
+}
+
+fn run_hotkey_action(action: HotkeyAction) {
+  match action {
+    HotkeyAction::ToggleActivation => kapt::toggle_kapt_activation(),
+    HotkeyAction::Kapture {
+      seconds,
+      seconds_after,
+    } => kapt::make_kapture(seconds, seconds_after),
+    HotkeyAction::AddMarker { kind } => kapt::mark_moment(kind),
+  }
+}
+
+// Reflects what happened in the background in the window and the tray
+fn handle_kapt_event(app: &tauri::AppHandle, event: KaptEvent) {
+  match event {
+    KaptEvent::ActivationToggled(active) => {
+      app
+        .emit_all("kapt_activation_toggled", active)
+        .expect("Failed to emit event");
+
+      app
+        .tray_handle()
+        .get_item("toggle_activate")
+        .set_title(if active { "Deactivate" } else { "Activate" })
+        .expect("Failed to set menu title");
+
+      toggle_kapture_menu_activation(app, active);
+    }
+    KaptEvent::KaptureProgress(progress) => {
+      let percents: Vec<u32> = {
+        let mut kapture_percents = KAPTURE_PERCENTS
+          .lock()
+          .expect("Failed to lock Kapture progress");
+
+        if progress.stage == KaptureStage::Finished {
+          kapture_percents.remove(&progress.kapture_id);
+        } else {
+          kapture_percents.insert(progress.kapture_id.clone(), progress.percent);
+        }
+
+        kapture_percents.values().copied().collect()
+      };
+
+      // The item cancels every Kapture, so it stays enabled until the last one is finished
+      let cancel_kapture_item = app.tray_handle().get_item("cancel_kapture");
+
+      cancel_kapture_item
+        .set_title(match percents.as_slice() {
+          [] => "Cancel Kapture".to_string(),
+          [percent] => format!("Cancel Kapture ({}%)", percent),
+          percents => format!("Cancel {} Kaptures", percents.len()),
+        })
+        .expect("Failed to set menu title");
+
+      cancel_kapture_item
+        .set_enabled(!percents.is_empty())
+        .expect("Failed to set enabled");
+
+      app
+        .emit_all("kapture_progress", progress)
+        .expect("Failed to emit event")
+    }
+    KaptEvent::KaptureCreated(kapture) => app
+      .emit_all("kapture_created", kapture)
+      .expect("Failed to emit event"),
+    KaptEvent::MarkersChanged(markers) => app
+      .emit_all("kapt_markers_changed", markers)
+      .expect("Failed to emit event"),
+    KaptEvent::Error(error) => app
+      .emit_all("kapt_error", error)
+      .expect("Failed to emit event"),
+  }
+}
+
+#[tauri::command]
+fn get_hotkeys() -> Result<Vec<HotkeyBinding>, KaptError> {
+  Ok(HOTKEYS.read()?.clone())
+}
+
+#[tauri::command]
+fn set_hotkeys(hotkeys: Vec<HotkeyBinding>) -> Result<(), KaptError> {
+  let app_handle = APP_HANDLE
+    .lock()?
+    .clone()
+    .ok_or_else(|| KaptError::InvalidSettings("Kapt hasn't started yet.".to_string()))?;
+  let previous_hotkeys = HOTKEYS.read()?.clone();
+
+  if let Err(e) = hotkeys::register_hotkeys(&app_handle, &hotkeys, run_hotkey_action) {
+    // Restore the previous shortcuts so that a conflict doesn't leave the user without any
+    if let Err(e) = hotkeys::register_hotkeys(&app_handle, &previous_hotkeys, run_hotkey_action) {
+      log::error!("{}", e);
+    }
+
+    return Err(e);
+  }
+
+  *HOTKEYS.write()? = hotkeys;
+  kapt::save_settings()
+}
 
 fn main() {
+  // The rolling buffer can run without a window, controlled through `kaptctl` or signals
+  if env::args().skip(1).any(|arg| arg == "--daemon") {
+    daemon::run_daemon();
+    return;
+  }
+
   let toggle_activate = CustomMenuItem::new("toggle_activate".to_string(), "Activate");
   let quit = CustomMenuItem::new("quit".to_string(), "Quit");
 
-  let seconds_options = vec![5, 10, 15, 30, 60];
   let mut kapture_menu = SystemTrayMenu::new();
-  for seconds_option in &seconds_options {
+  for seconds_option in &KAPTURE_SECONDS_OPTIONS {
     let menu_item = CustomMenuItem::new(
       format!("kapture_seconds_{}", seconds_option),
       format!("{} Seconds", seconds_option),
@@ -85,104 +486,154 @@ fn main() {
     kapture_menu = kapture_menu.add_item(menu_item.disabled());
   }
 
-  let toggle_kapture_menu_activation = move |app: &tauri::AppHandle, enabled: bool| {
-    for seconds_option in &seconds_options {
-      app
-        .tray_handle()
-        .get_item(&format!("kapture_seconds_{}", seconds_option))
-        .set_enabled(enabled)
-        .expect("Failed to set enabled");
-    }
-  };
+  kapture_menu = kapture_menu.add_native_item(SystemTrayMenuItem::Separator);
+  for (seconds, seconds_after) in &KAPTURE_AROUND_OPTIONS {
+    let menu_item = CustomMenuItem::new(
+      get_kapture_around_id(*seconds, *seconds_after),
+      format!("{}s Before + {}s After", seconds, seconds_after),
+    );
+
+    kapture_menu = kapture_menu.add_item(menu_item.disabled());
+  }
 
   let kapture_submenu = SystemTraySubmenu::new("Kapture", kapture_menu);
 
+  let selected_kapture_format = RECORDER
+    .config()
+    .map(|config| config.kapture_format)
+    .unwrap_or_default();
+  let mut kapture_format_menu = SystemTrayMenu::new();
+  for format in &KAPTURE_FORMATS {
+    kapture_format_menu = kapture_format_menu.add_item(CustomMenuItem::new(
+      format!("kapture_format_{}", format.extension()),
+      get_kapture_format_title(*format, selected_kapture_format),
+    ));
+  }
+
+  let kapture_format_submenu = SystemTraySubmenu::new("Format", kapture_format_menu);
+  let cancel_kapture = CustomMenuItem::new("cancel_kapture".to_string(), "Cancel Kapture");
+
   let tray_menu = SystemTrayMenu::new()
     .add_item(toggle_activate)
     .add_submenu(kapture_submenu)
+    .add_submenu(kapture_format_submenu)
+    .add_item(cancel_kapture.disabled())
     .add_native_item(SystemTrayMenuItem::Separator)
     .add_item(quit);
 
   let system_tray = SystemTray::new().with_menu(tray_menu);
 
   tauri::Builder::default()
+    .setup(|app| {
+      let app_handle = app.handle();
+      let mut events = RECORDER.subscribe_events();
+      tauri::async_runtime::spawn(async move {
+        loop {
+          match events.recv().await {
+            Ok(event) => handle_kapt_event(&app_handle, event),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+          }
+        }
+      });
+
+      // A taken shortcut shouldn't keep Kapt from starting, so it's only reported
+      let app_handle = app.handle();
+      let hotkeys = HOTKEYS.read().map_err(KaptError::from)?.clone();
+      if let Err(e) = hotkeys::register_hotkeys(&app_handle, &hotkeys, run_hotkey_action) {
+        RECORDER.report_error(e);
+      }
+      *APP_HANDLE.lock().map_err(KaptError::from)? = Some(app_handle);
+
+      // Lets `kaptctl` control Kapt
+      tauri::async_runtime::spawn(async {
+        if let Err(e) = control_server::serve_control_socket().await {
+          RECORDER.report_error(e);
+        }
+      });
+
+      Ok(())
+    })
     .system_tray(system_tray)
-    .on_system_tray_event(move |app, event| {
-      match event {
-        SystemTrayEvent::MenuItemClick { id, .. } => {
-          let item_handle = app.tray_handle().get_item(&id);
-
-          match id.as_str() {
-            "toggle_activate" => {
-              let state = &*KAPT_STATE.read().expect("Failed to get read lock");
-
-              // If Kapt is active, deactivate it
-              if state.is_active() {
-                tauri::async_runtime::spawn(async {
-                  recording::deactivate_kapt(&*KAPT_STATE).await;
-                });
-
-                app
-                  .emit_all("kapt_activation_toggled", false)
-                  .expect("Failed to emit event");
-
-                item_handle
-                  .set_title("Activate")
-                  .expect("Failed to set menu title");
-
-                toggle_kapture_menu_activation(app, false);
-              } else {
-                tauri::async_runtime::spawn(async {
-                  recording::activate_kapt(&*KAPT_STATE).await;
-                });
-
-                app
-                  .emit_all("kapt_activation_toggled", true)
-                  .expect("Failed to emit event");
-
-                item_handle
-                  .set_title("Deactivate")
-                  .expect("Failed to set menu title");
-
-                toggle_kapture_menu_activation(app, true);
-              }
-            }
-            "quit" => {
-              std::process::exit(0);
-            }
-            id => {
-              if id.starts_with("kapture_seconds") {
-                let seconds = id
-                  .replace("kapture_seconds_", "")
-                  .parse::<u32>()
-                  .expect("Failed to parse");
-
-                let timestamp = get_current_time();
-                let app_handle = app.clone();
-                tauri::async_runtime::spawn(async move {
-                  let video_path = kapture::create_kapture(&*KAPT_STATE, timestamp, seconds).await;
-                  println!("caputer created");
-                  app_handle
-                    .emit_all("kapture_created", video_path)
-                    .expect("Failed to emit event");
-                })
-              }
+    .on_system_tray_event(move |app, event| match event {
+      SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+        "toggle_activate" => kapt::toggle_kapt_activation(),
+        "cancel_kapture" => {
+          if let Err(e) = RECORDER.cancel_kapture(None) {
+            RECORDER.report_error(e);
+          }
+        }
+        "quit" => {
+          control_server::remove_control_socket();
+          std::process::exit(0);
+        }
+        id => {
+          if id.starts_with("kapture_seconds") {
+            let seconds = id
+              .replace("kapture_seconds_", "")
+              .parse::<u32>()
+              .expect("Failed to parse");
+
+            kapt::make_kapture(seconds, 0);
+          } else if let Some((seconds, seconds_after)) = KAPTURE_AROUND_OPTIONS
+            .iter()
+            .find(|(seconds, seconds_after)| id == get_kapture_around_id(*seconds, *seconds_after))
+          {
+            kapt::make_kapture(*seconds, *seconds_after);
+          } else if let Some(format) = KAPTURE_FORMATS
+            .iter()
+            .find(|format| id == format!("kapture_format_{}", format.extension()))
+          {
+            let format = *format;
+            match kapt::update_config(|config| config.kapture_format = format) {
+              Ok(_) => update_kapture_format_menu(app, format),
+              Err(e) => RECORDER.report_error(e),
             }
           }
         }
-        _ => {}
-      }
+      },
+      _ => {}
     })
-    .manage(&*KAPT_STATE)
+    .manage(RECORDER.clone())
     .invoke_handler(tauri::generate_handler![
       activate_kapt,
       deactivate_kapt,
       create_kapture,
+      cancel_kapture,
+      add_marker,
+      remove_marker,
+      get_markers,
+      export_range,
+      list_kaptures,
+      search_kaptures,
+      rename_kapture,
+      tag_kapture,
+      delete_kapture,
+      get_kapture_format,
+      set_kapture_format,
       get_audio_sources,
       set_audio_source,
       select_video_folder,
       set_video_folder,
-      set_max_seconds_cached
+      set_max_seconds_cached,
+      set_max_cache_megabytes,
+      get_buffer_status,
+      get_hotkeys,
+      set_hotkeys,
+      get_capture_backend,
+      set_capture_backend,
+      get_capture_mode,
+      get_capture_geometry,
+      get_monitors,
+      set_capture_monitor,
+      set_capture_region,
+      select_capture_region,
+      get_windows,
+      set_capture_window,
+      get_encoding_profile,
+      set_encoding_profile,
+      get_encoding_preset_names,
+      set_encoding_preset
     ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use kapt_core::backend::CaptureBackendKind;
+use kapt_core::display::{self, CaptureMode};
+use kapt_core::encoding::{EncodingProfile, KaptureFormat};
+use kapt_core::{KaptError, RecorderConfig};
+
+use crate::hotkeys::{default_hotkeys, HotkeyBinding};
+
+// Bump this and add a case to `migrate_settings` whenever a field is renamed or its meaning
+// changes; fields that are simply added are filled in with their defaults
+pub const SETTINGS_VERSION: u64 = 1;
+
+// The part of the recorder's configuration that survives a restart, and the hotkeys
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+  pub version: u64,
+  pub audio_source: usize,
+  pub capture_mode: CaptureMode,
+  pub video_folder: Option<String>,
+  pub max_seconds_cached: u32,
+  pub max_cache_megabytes: Option<u32>,
+  pub encoding_profile: EncodingProfile,
+  pub kapture_format: KaptureFormat,
+  pub hotkeys: Vec<HotkeyBinding>,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self::new(&RecorderConfig::default(), &default_hotkeys())
+  }
+}
+
+impl Settings {
+  pub fn new(config: &RecorderConfig, hotkeys: &[HotkeyBinding]) -> Self {
+    Self {
+      version: SETTINGS_VERSION,
+      audio_source: config.audio_source,
+      capture_mode: config.capture_mode.clone(),
+      video_folder: config.video_folder.clone(),
+      max_seconds_cached: config.max_seconds_cached,
+      max_cache_megabytes: config.max_cache_megabytes,
+      encoding_profile: config.encoding_profile.clone(),
+      kapture_format: config.kapture_format,
+      hotkeys: hotkeys.to_vec(),
+    }
+  }
+
+  // The capture backend isn't saved since it depends on the session Kapt is started in
+  pub fn recorder_config(&self) -> RecorderConfig {
+    RecorderConfig {
+      audio_source: self.audio_source,
+      capture_backend: CaptureBackendKind::from_env(),
+      capture_mode: self.capture_mode.clone(),
+      video_folder: self.video_folder.clone(),
+      max_seconds_cached: self.max_seconds_cached,
+      max_cache_megabytes: self.max_cache_megabytes,
+      encoding_profile: self.encoding_profile.clone(),
+      kapture_format: self.kapture_format,
+    }
+  }
+}
+
+// `$XDG_CONFIG_HOME/kapt/settings.json`, falling back to `~/.config`
+pub fn get_settings_path() -> Option<PathBuf> {
+  let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+    Some(config_dir) if !config_dir.is_empty() => PathBuf::from(config_dir),
+    _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+  };
+
+  Some(config_dir.join("kapt").join("settings.json"))
+}
+
+// Upgrades settings written by older versions of Kapt to the current format, one version at
+// a time (e.g. `if version < 2 { ... }` for the next format change)
+fn migrate_settings(mut settings: Value) -> Value {
+  // Files without a version were written before settings were versioned, which only added
+  // the version field
+  let version = settings.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+  if version > SETTINGS_VERSION {
+    log::warn!(
+      "The settings were written by a newer version of Kapt (version {}).",
+      version
+    );
+    return settings;
+  }
+
+  if let Some(settings) = settings.as_object_mut() {
+    settings.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+  }
+
+  settings
+}
+
+// Loads the saved settings, using the defaults if there are none or they can't be read
+pub fn load_settings() -> Settings {
+  let settings_path = match get_settings_path() {
+    Some(settings_path) => settings_path,
+    None => return Settings::default(),
+  };
+
+  let settings = match fs::read_to_string(&settings_path) {
+    Ok(settings) => settings,
+    Err(_) => return Settings::default(),
+  };
+
+  let settings = serde_json::from_str::<Value>(&settings)
+    .map(migrate_settings)
+    .and_then(serde_json::from_value::<Settings>);
+
+  match settings {
+    Ok(mut settings) => {
+      // Window IDs don't survive the window being closed, let alone a restart
+      if let CaptureMode::Window { .. } = settings.capture_mode {
+        settings.capture_mode = CaptureMode::Screen;
+      }
+
+      // The screen might have shrunk since the region was picked (e.g. a monitor was unplugged)
+      if let CaptureMode::Region(region) = &settings.capture_mode {
+        if let Some(screen_geometry) = display::get_screen_geometry() {
+          if let Err(e) = region.validate(&screen_geometry) {
+            log::warn!(
+              "Capturing the whole screen instead of the saved region: {}",
+              e
+            );
+            settings.capture_mode = CaptureMode::Screen;
+          }
+        }
+      }
+
+      settings
+    }
+    Err(e) => {
+      log::error!("Failed to read {}: {}", settings_path.display(), e);
+      Settings::default()
+    }
+  }
+}
+
+pub fn save_settings(settings: &Settings) -> Result<(), KaptError> {
+  let settings_path = get_settings_path()
+    .ok_or_else(|| KaptError::Io("Failed to find the config directory.".to_string()))?;
+
+  if let Some(settings_dir) = settings_path.parent() {
+    fs::create_dir_all(settings_dir)?;
+  }
+
+  let settings =
+    serde_json::to_string_pretty(settings).map_err(|e| KaptError::Io(e.to_string()))?;
+
+  // Writing to a temporary file first so that a crash can't leave a truncated file behind
+  let temp_settings_path = settings_path.with_extension("json.tmp");
+  fs::write(&temp_settings_path, settings)?;
+  fs::rename(&temp_settings_path, &settings_path)?;
+
+  Ok(())
+}
@@ -2,8 +2,10 @@ import { reactive } from 'vue';
 
 type KaptFrontendState = {
   kaptureObjectUrl: string | null;
+  kaptureMimeType: string;
 };
 
 export const state = reactive<KaptFrontendState>({
   kaptureObjectUrl: null,
+  kaptureMimeType: 'video/mp4',
 });